@@ -0,0 +1,570 @@
+use alloy::primitives::{Address, U256};
+use eyre::Result;
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+
+use chain_model::{amm, ArbHop, ArbKind, ArbOpportunity, Pair, SyncEvent, Token};
+
+use crate::init::ArbConfig;
+
+const BPS: f64 = 10_000.0;
+
+struct Pool {
+    pair: Pair,
+    reserve0: U256,
+    reserve1: U256,
+    fee_bps: u32,
+}
+
+impl Pool {
+    /// `(reserve_in, reserve_out)` when swapping `token_in`.
+    fn reserves(&self, token_in: Address) -> (U256, U256) {
+        if token_in == self.pair.token0.address {
+            (self.reserve0, self.reserve1)
+        } else {
+            (self.reserve1, self.reserve0)
+        }
+    }
+
+    fn other(&self, token: Address) -> Address {
+        if token == self.pair.token0.address {
+            self.pair.token1.address
+        } else {
+            self.pair.token0.address
+        }
+    }
+
+    fn token(&self, address: Address) -> &Token {
+        if address == self.pair.token0.address {
+            &self.pair.token0
+        } else {
+            &self.pair.token1
+        }
+    }
+}
+
+/// A cycle as `(pair, token_in)` per hop, closing back on the first token.
+type Cycle = Vec<(Address, Address)>;
+
+/// Finds cycles through a freshly synced pair whose output exceeds their input:
+/// the same two tokens on another venue, or a triangle through any token
+/// paired with both of them.
+///
+/// The optimal input comes from collapsing the cycle into one virtual
+/// constant-product pool, and is then replayed hop by hop with the exact
+/// integer math of the pairs.
+pub struct Detector {
+    pools: HashMap<Address, Pool>,
+    by_tokens: HashMap<(Address, Address), Vec<Address>>,
+    /// Tokens each token has a pair with.
+    neighbours: HashMap<Address, HashSet<Address>>,
+    fee_bps: u32,
+    pair_fee_bps: HashMap<Address, u32>,
+    triangular: bool,
+    profit_tokens: Vec<(Address, f64)>,
+}
+
+impl Detector {
+    pub fn new(cfg: &ArbConfig) -> Result<Self> {
+        Ok(Self {
+            pools: HashMap::new(),
+            by_tokens: HashMap::new(),
+            neighbours: HashMap::new(),
+            fee_bps: cfg.fee_bps,
+            pair_fee_bps: cfg
+                .pair_fee_bps
+                .iter()
+                .map(|(pair, fee)| Ok((Address::from_str(pair)?, *fee)))
+                .collect::<Result<_>>()?,
+            triangular: cfg.triangular,
+            profit_tokens: cfg
+                .profit_tokens
+                .iter()
+                .map(|token| Ok((Address::from_str(&token.address)?, token.min_profit)))
+                .collect::<Result<_>>()?,
+        })
+    }
+
+    pub fn contains(&self, pair: &Address) -> bool {
+        self.pools.contains_key(pair)
+    }
+
+    pub fn len(&self) -> usize {
+        self.pools.len()
+    }
+
+    /// Stores the reserves of `event`; `pair` is required the first time.
+    pub fn apply(&mut self, event: &SyncEvent, pair: Option<Pair>) {
+        let reserve0 = U256::from(event.reserve0);
+        let reserve1 = U256::from(event.reserve1);
+        if let Some(pool) = self.pools.get_mut(&event.pair) {
+            pool.reserve0 = reserve0;
+            pool.reserve1 = reserve1;
+            return;
+        }
+        let Some(pair) = pair else {
+            return;
+        };
+        let (token0, token1) = (pair.token0.address, pair.token1.address);
+        self.by_tokens
+            .entry(token_set(token0, token1))
+            .or_default()
+            .push(pair.address);
+        self.neighbours.entry(token0).or_default().insert(token1);
+        self.neighbours.entry(token1).or_default().insert(token0);
+        let fee_bps = self
+            .pair_fee_bps
+            .get(&pair.address)
+            .copied()
+            .unwrap_or(self.fee_bps);
+        self.pools.insert(
+            event.pair,
+            Pool {
+                pair,
+                reserve0,
+                reserve1,
+                fee_bps,
+            },
+        );
+    }
+
+    /// Opportunities through `event.pair` at its current reserves.
+    pub fn detect(&self, event: &SyncEvent) -> Vec<ArbOpportunity> {
+        let Some(pool) = self.pools.get(&event.pair) else {
+            return Vec::new();
+        };
+        let (a, b) = (pool.pair.token0.address, pool.pair.token1.address);
+        let trigger = pool.pair.address;
+
+        let mut cycles: Vec<(ArbKind, Cycle)> = Vec::new();
+        for other in self.pairs_of(a, b) {
+            if other != trigger {
+                cycles.push((ArbKind::CrossVenue, vec![(trigger, a), (other, b)]));
+                cycles.push((ArbKind::CrossVenue, vec![(other, a), (trigger, b)]));
+            }
+        }
+        if self.triangular {
+            for c in self.common_neighbours(a, b) {
+                for via_a in self.pairs_of(a, c) {
+                    for via_b in self.pairs_of(b, c) {
+                        cycles.push((
+                            ArbKind::Triangular,
+                            vec![(trigger, a), (via_b, b), (via_a, c)],
+                        ));
+                        cycles.push((
+                            ArbKind::Triangular,
+                            vec![(via_a, a), (via_b, c), (trigger, b)],
+                        ));
+                    }
+                }
+            }
+        }
+
+        cycles
+            .into_iter()
+            .filter_map(|(kind, cycle)| self.best_start(kind, &cycle, trigger, event))
+            .collect()
+    }
+
+    /// Tokens paired with both `a` and `b`, closing a triangle with them.
+    fn common_neighbours(&self, a: Address, b: Address) -> Vec<Address> {
+        let (Some(of_a), Some(of_b)) = (self.neighbours.get(&a), self.neighbours.get(&b)) else {
+            return Vec::new();
+        };
+        let (small, large) = if of_a.len() <= of_b.len() {
+            (of_a, of_b)
+        } else {
+            (of_b, of_a)
+        };
+        small
+            .iter()
+            .filter(|&&c| c != a && c != b && large.contains(&c))
+            .copied()
+            .collect()
+    }
+
+    fn pairs_of(&self, a: Address, b: Address) -> Vec<Address> {
+        self.by_tokens
+            .get(&token_set(a, b))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Evaluates `cycle` from every profit token it passes through and keeps
+    /// the start whose gross profit clears its minimum by the widest factor.
+    /// Cycles through no profit token are not reported.
+    fn best_start(
+        &self,
+        kind: ArbKind,
+        cycle: &Cycle,
+        trigger: Address,
+        event: &SyncEvent,
+    ) -> Option<ArbOpportunity> {
+        self.profit_tokens
+            .iter()
+            .filter_map(|&(start, min_profit)| {
+                let offset = cycle.iter().position(|(_, token_in)| *token_in == start)?;
+                let mut rotated = cycle.clone();
+                rotated.rotate_left(offset);
+
+                let opportunity = self.evaluate(kind, &rotated, trigger, event)?;
+                let margin = opportunity.gross_profit_units / min_profit.max(f64::MIN_POSITIVE);
+                (opportunity.gross_profit_units >= min_profit).then_some((margin, opportunity))
+            })
+            .max_by(|(a, _), (b, _)| a.total_cmp(b))
+            .map(|(_, opportunity)| opportunity)
+    }
+
+    fn evaluate(
+        &self,
+        kind: ArbKind,
+        cycle: &Cycle,
+        trigger: Address,
+        event: &SyncEvent,
+    ) -> Option<ArbOpportunity> {
+        let pools = cycle
+            .iter()
+            .map(|(pair, _)| self.pools.get(pair))
+            .collect::<Option<Vec<_>>>()?;
+
+        // Collapse the hops into virtual reserves (e0, e1); the cycle then
+        // pays `gamma * e1 * x / (e0 + gamma * x)` for an input `x`.
+        let mut virtual_reserves: Option<(f64, f64)> = None;
+        let mut gamma = 1.0;
+        for (pool, (_, token_in)) in pools.iter().zip(cycle) {
+            let (reserve_in, reserve_out) = pool.reserves(*token_in);
+            if reserve_in.is_zero() || reserve_out.is_zero() {
+                return None;
+            }
+            let (r_in, r_out) = (f64::from(reserve_in), f64::from(reserve_out));
+            let hop_gamma = (BPS - pool.fee_bps as f64) / BPS;
+            virtual_reserves = Some(match virtual_reserves {
+                None => {
+                    gamma = hop_gamma;
+                    (r_in, r_out)
+                }
+                Some((e0, e1)) => {
+                    let denominator = r_in + hop_gamma * e1;
+                    (
+                        e0 * r_in / denominator,
+                        hop_gamma * e1 * r_out / denominator,
+                    )
+                }
+            });
+        }
+        let (e0, e1) = virtual_reserves?;
+        if gamma * e1 <= e0 {
+            return None;
+        }
+        let optimal = ((gamma * e0 * e1).sqrt() - e0) / gamma;
+        if !optimal.is_finite() || optimal < 1.0 {
+            return None;
+        }
+
+        // Exact replay with the pairs' integer math.
+        let amount_in = U256::from(optimal as u128);
+        let mut amount = amount_in;
+        let mut hops = Vec::with_capacity(cycle.len());
+        for (pool, (pair, token_in)) in pools.iter().zip(cycle) {
+            let (reserve_in, reserve_out) = pool.reserves(*token_in);
+            let amount_out = amm::get_amount_out(amount, reserve_in, reserve_out, pool.fee_bps)?;
+            hops.push(ArbHop {
+                pair: *pair,
+                token_in: *token_in,
+                token_out: pool.other(*token_in),
+                amount_in: amount,
+                amount_out,
+                fee_bps: pool.fee_bps,
+            });
+            amount = amount_out;
+        }
+        if amount <= amount_in {
+            return None;
+        }
+
+        let start = cycle.first()?.1;
+        let token = pools.first()?.token(start);
+        let gross_profit = amount - amount_in;
+        Some(ArbOpportunity {
+            kind,
+            token: start,
+            symbol: token.symbol.clone(),
+            hops,
+            amount_in,
+            amount_out: amount,
+            gross_profit,
+            gross_profit_units: f64::from(gross_profit) / 10f64.powi(token.decimals as i32),
+            trigger_pair: trigger,
+            block_number: event.block_number,
+            block_timestamp: event.block_timestamp,
+        })
+    }
+}
+
+/// Unordered token set of a pair.
+fn token_set(a: Address, b: Address) -> (Address, Address) {
+    if a < b {
+        (a, b)
+    } else {
+        (b, a)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::init::ProfitTokenConfig;
+    use alloy::primitives::{address, FixedBytes};
+
+    const WETH: Address = address!("C02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2");
+    const USDC: Address = address!("A0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48");
+    const DAI: Address = address!("6B175474E89094C44Da98b954EedeAC495271d0F");
+
+    fn detector(profit_tokens: &[(Address, f64)]) -> Detector {
+        Detector::new(&ArbConfig {
+            fee_bps: 30,
+            pair_fee_bps: HashMap::new(),
+            triangular: true,
+            profit_tokens: profit_tokens
+                .iter()
+                .map(|(address, min_profit)| ProfitTokenConfig {
+                    address: address.to_string(),
+                    min_profit: *min_profit,
+                })
+                .collect(),
+        })
+        .unwrap()
+    }
+
+    fn token(address: Address) -> Token {
+        let (symbol, decimals) = match address {
+            WETH => ("WETH", 18),
+            USDC => ("USDC", 6),
+            _ => ("DAI", 18),
+        };
+        Token {
+            address,
+            decimals,
+            symbol: symbol.to_string(),
+            total_supply: U256::ZERO,
+            verified: true,
+            risk: None,
+        }
+    }
+
+    fn units(amount: u64, address: Address) -> U256 {
+        U256::from(amount) * U256::from(10).pow(U256::from(token(address).decimals))
+    }
+
+    /// Adds a pool holding `amount0` token0 and `amount1` token1 in whole
+    /// tokens, and returns its Sync.
+    fn pool(
+        detector: &mut Detector,
+        address: Address,
+        (token0, amount0): (Address, u64),
+        (token1, amount1): (Address, u64),
+    ) -> SyncEvent {
+        let event = SyncEvent {
+            pair: address,
+            reserve0: units(amount0, token0).to(),
+            reserve1: units(amount1, token1).to(),
+            transaction_hash: FixedBytes::ZERO,
+            log_index: Some(0),
+            block_number: 1,
+            block_timestamp: 0,
+            snapshot: false,
+        };
+        let pair = Pair {
+            address,
+            token0: token(token0),
+            token1: token(token1),
+            factory: None,
+            created_block: None,
+        };
+        detector.apply(&event, Some(pair));
+        event
+    }
+
+    /// Profit of routing `amount_in` through `hops` of `(reserve_in,
+    /// reserve_out)`, by the pairs' integer math.
+    fn profit(hops: &[(U256, U256)], amount_in: U256) -> U256 {
+        let amount_out = hops.iter().try_fold(amount_in, |amount, (r_in, r_out)| {
+            amm::get_amount_out(amount, *r_in, *r_out, 30)
+        });
+        amount_out.map_or(U256::ZERO, |out| out.saturating_sub(amount_in))
+    }
+
+    /// Best profit over inputs of `1..=steps` times `step`.
+    fn brute_force(hops: &[(U256, U256)], step: U256, steps: u64) -> U256 {
+        (1..=steps)
+            .map(|i| profit(hops, step * U256::from(i)))
+            .max()
+            .unwrap()
+    }
+
+    fn hop_reserves(opportunity: &ArbOpportunity, detector: &Detector) -> Vec<(U256, U256)> {
+        opportunity
+            .hops
+            .iter()
+            .map(|hop| detector.pools[&hop.pair].reserves(hop.token_in))
+            .collect()
+    }
+
+    fn assert_consistent(opportunity: &ArbOpportunity) {
+        for (hop, next) in opportunity.hops.iter().zip(&opportunity.hops[1..]) {
+            assert_eq!(hop.amount_out, next.amount_in);
+            assert_eq!(hop.token_out, next.token_in);
+        }
+        let (first, last) = (&opportunity.hops[0], opportunity.hops.last().unwrap());
+        assert_eq!(
+            (first.token_in, last.token_out),
+            (opportunity.token, opportunity.token)
+        );
+        assert_eq!(first.amount_in, opportunity.amount_in);
+        assert_eq!(last.amount_out, opportunity.amount_out);
+        assert_eq!(
+            opportunity.gross_profit,
+            opportunity.amount_out - opportunity.amount_in
+        );
+    }
+
+    #[test]
+    fn cross_venue_input_is_optimal() {
+        let mut detector = detector(&[(USDC, 0.0)]);
+        pool(
+            &mut detector,
+            address!("0000000000000000000000000000000000000b0b"),
+            (USDC, 200_000),
+            (WETH, 100),
+        );
+        let trigger = pool(
+            &mut detector,
+            address!("0000000000000000000000000000000000000a0a"),
+            (USDC, 250_000),
+            (WETH, 100),
+        );
+
+        let opportunities = detector.detect(&trigger);
+        assert_eq!(opportunities.len(), 1);
+        let opportunity = &opportunities[0];
+        assert_eq!(opportunity.kind, ArbKind::CrossVenue);
+        assert_eq!(opportunity.token, USDC);
+        assert_consistent(opportunity);
+
+        // No input on a grid of 10 USDC up to 50k does better.
+        let hops = hop_reserves(opportunity, &detector);
+        let best = brute_force(&hops, units(10, USDC), 5_000);
+        assert!(opportunity.gross_profit >= best);
+        assert_eq!(
+            profit(&hops, opportunity.amount_in),
+            opportunity.gross_profit
+        );
+    }
+
+    #[test]
+    fn triangle_collapses_into_one_virtual_pool() {
+        let mut detector = detector(&[(WETH, 0.0)]);
+        pool(
+            &mut detector,
+            address!("0000000000000000000000000000000000000c0c"),
+            (USDC, 1_000_000),
+            (DAI, 1_000_000),
+        );
+        pool(
+            &mut detector,
+            address!("0000000000000000000000000000000000000b0b"),
+            (WETH, 100),
+            (DAI, 200_000),
+        );
+        let trigger = pool(
+            &mut detector,
+            address!("0000000000000000000000000000000000000a0a"),
+            (USDC, 250_000),
+            (WETH, 100),
+        );
+
+        let opportunities = detector.detect(&trigger);
+        assert_eq!(opportunities.len(), 1);
+        let opportunity = &opportunities[0];
+        assert_eq!(opportunity.kind, ArbKind::Triangular);
+        assert_eq!(opportunity.hops.len(), 3);
+        assert_eq!(opportunity.token, WETH);
+        assert_consistent(opportunity);
+
+        // No input on a grid of 0.01 WETH up to 20 WETH does better.
+        let hops = hop_reserves(opportunity, &detector);
+        let best = brute_force(&hops, units(1, WETH) / U256::from(100), 2_000);
+        assert!(opportunity.gross_profit >= best);
+    }
+
+    #[test]
+    fn fees_absorb_small_price_gaps() {
+        let mut detector = detector(&[(USDC, 0.0)]);
+        pool(
+            &mut detector,
+            address!("0000000000000000000000000000000000000b0b"),
+            (USDC, 250_000),
+            (WETH, 100),
+        );
+        // 0.4% apart, below the 0.6% two swaps cost.
+        let trigger = pool(
+            &mut detector,
+            address!("0000000000000000000000000000000000000a0a"),
+            (USDC, 251_000),
+            (WETH, 100),
+        );
+        assert!(detector.detect(&trigger).is_empty());
+    }
+
+    #[test]
+    fn cycles_need_a_profit_token_and_its_minimum() {
+        let setup = |profit_tokens: &[(Address, f64)]| {
+            let mut detector = detector(profit_tokens);
+            pool(
+                &mut detector,
+                address!("0000000000000000000000000000000000000b0b"),
+                (USDC, 200_000),
+                (WETH, 100),
+            );
+            let trigger = pool(
+                &mut detector,
+                address!("0000000000000000000000000000000000000a0a"),
+                (USDC, 250_000),
+                (WETH, 100),
+            );
+            detector.detect(&trigger)
+        };
+        assert!(setup(&[]).is_empty());
+        assert!(setup(&[(DAI, 0.0)]).is_empty());
+        assert!(setup(&[(USDC, 1_000_000.0)]).is_empty());
+        assert_eq!(setup(&[(USDC, 1.0)]).len(), 1);
+    }
+
+    #[test]
+    fn start_clearing_its_minimum_widest_wins() {
+        let setup = |profit_tokens: &[(Address, f64)]| {
+            let mut detector = detector(profit_tokens);
+            pool(
+                &mut detector,
+                address!("0000000000000000000000000000000000000b0b"),
+                (USDC, 200_000),
+                (WETH, 100),
+            );
+            let trigger = pool(
+                &mut detector,
+                address!("0000000000000000000000000000000000000a0a"),
+                (USDC, 250_000),
+                (WETH, 100),
+            );
+            detector.detect(&trigger).remove(0)
+        };
+        let usdc = setup(&[(USDC, 0.0)]).gross_profit_units;
+        let weth = setup(&[(WETH, 0.0)]).gross_profit_units;
+
+        // Config order does not matter, the margin over the minimum does.
+        let start = setup(&[(USDC, usdc / 2.0), (WETH, weth / 4.0)]);
+        assert_eq!(start.token, WETH);
+        let start = setup(&[(USDC, usdc / 4.0), (WETH, weth / 2.0)]);
+        assert_eq!(start.token, USDC);
+    }
+}
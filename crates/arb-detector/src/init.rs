@@ -0,0 +1,116 @@
+use clap::Parser;
+use config::{Config, File};
+use eyre::Result;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+use tracing::debug;
+use tracing_subscriber::{fmt, prelude::*, EnvFilter};
+
+#[derive(Debug, Deserialize)]
+pub struct AppConfig {
+    pub nats: NatsConfig,
+    pub arb: ArbConfig,
+    pub log: Option<LogConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NatsConfig {
+    pub server_url: String,
+    pub subject_sync: String,
+    pub stream_name: String,
+    pub kv_bucket: String,
+    pub subject_output: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ArbConfig {
+    /// Swap fee in basis points, 30 for Uniswap V2.
+    pub fee_bps: u32,
+    /// Fee per pair address, for pairs of forks with another fee.
+    #[serde(default)]
+    pub pair_fee_bps: HashMap<String, u32>,
+    /// Also look for three-pair cycles through any token paired with both
+    /// tokens of the synced pair.
+    pub triangular: bool,
+    /// Tokens a cycle may start in. A cycle is evaluated from each of them it
+    /// passes through and reported from the start that clears its minimum by
+    /// the widest factor; a cycle through none of them is not reported.
+    pub profit_tokens: Vec<ProfitTokenConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ProfitTokenConfig {
+    pub address: String,
+    /// Minimum gross profit to publish, in whole tokens.
+    pub min_profit: f64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LogConfig {
+    pub level: String,
+}
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Cli {
+    #[arg(long)]
+    server_url: Option<String>,
+    #[arg(long)]
+    subject_sync: Option<String>,
+    #[arg(long)]
+    stream_name: Option<String>,
+    #[arg(long)]
+    kv_bucket: Option<String>,
+    #[arg(long)]
+    subject_output: Option<String>,
+    #[arg(long)]
+    fee_bps: Option<u32>,
+    #[arg(long)]
+    triangular: Option<bool>,
+}
+
+impl AppConfig {
+    pub fn from_file_or_cli() -> Result<Self> {
+        let cli = Cli::parse();
+        let config_path1 = Path::new("config/arb-detector.toml");
+        let config_path2 = Path::new("arb-detector.toml");
+
+        let cfg: AppConfig = Config::builder()
+            .set_default("arb.fee_bps", 30)?
+            .set_default("arb.triangular", true)?
+            .add_source(File::from(config_path1).required(false))
+            .add_source(File::from(config_path2).required(false))
+            .set_override_option("nats.server_url", cli.server_url)?
+            .set_override_option("nats.subject_sync", cli.subject_sync)?
+            .set_override_option("nats.stream_name", cli.stream_name)?
+            .set_override_option("nats.kv_bucket", cli.kv_bucket)?
+            .set_override_option("nats.subject_output", cli.subject_output)?
+            .set_override_option("arb.fee_bps", cli.fee_bps)?
+            .set_override_option("arb.triangular", cli.triangular)?
+            .build()
+            .map_err(eyre::Report::from)?
+            .try_deserialize()?;
+        Ok(cfg)
+    }
+
+    pub fn init_log(&self) -> Result<()> {
+        let level_str = self
+            .log
+            .as_ref()
+            .map(|l| l.level.as_str())
+            .unwrap_or("info");
+
+        let filter = EnvFilter::try_from_default_env()
+            .or_else(|_| EnvFilter::try_new(level_str))
+            .map_err(eyre::Report::from)?;
+
+        tracing_subscriber::registry()
+            .with(fmt::layer())
+            .with(filter)
+            .init();
+
+        debug!("log level configured to: '{}'", level_str);
+        Ok(())
+    }
+}
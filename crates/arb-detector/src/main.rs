@@ -0,0 +1,87 @@
+use async_nats::jetstream::kv::Store;
+use eyre::{eyre, Result};
+use futures_util::StreamExt;
+use tracing::{debug, info, warn};
+
+use chain_model::{Pair, SyncEvent};
+
+mod detector;
+mod init;
+mod mq;
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let app_cfg = init::AppConfig::from_file_or_cli()?;
+    app_cfg.init_log()?;
+    info!("starting arb-detector with config: {app_cfg:#?}");
+
+    let mq_client = mq::MqClient::new(&app_cfg.nats.server_url, &app_cfg.nats.stream_name).await?;
+    let kv = mq_client.kv_store(&app_cfg.nats.kv_bucket).await?;
+    let mut detector = detector::Detector::new(&app_cfg.arb)?;
+
+    // Reserves are rebuilt from the whole stream; opportunities are only
+    // published once the replay caught up with the head.
+    let mut live = false;
+    let mut syncs = mq_client
+        .jetstream_replay(&app_cfg.nats.subject_sync)
+        .await?;
+    while let Some(msg_result) = syncs.next().await {
+        let msg = msg_result?;
+        let event = match serde_json::from_slice::<SyncEvent>(&msg.payload) {
+            Ok(event) => event,
+            Err(e) => {
+                warn!("invalid sync payload: {e}");
+                continue;
+            }
+        };
+
+        // A pair whose metadata cannot be read is tried again on its next sync.
+        let pair = if detector.contains(&event.pair) {
+            None
+        } else {
+            match pair_metadata(&kv, &event).await {
+                Ok(pair) => pair,
+                Err(e) => {
+                    warn!("metadata of pair {} unavailable: {e}", event.pair);
+                    None
+                }
+            }
+        };
+        detector.apply(&event, pair);
+
+        if !live {
+            live = msg.info().map_err(|e| eyre!("message info: {e}"))?.pending == 0;
+            if !live {
+                continue;
+            }
+            info!("replay done, watching {} pairs", detector.len());
+        }
+
+        for opportunity in detector.detect(&event) {
+            info!(
+                "{:?} arb of {} {} over {} hops at block {}",
+                opportunity.kind,
+                opportunity.gross_profit_units,
+                opportunity.symbol,
+                opportunity.hops.len(),
+                opportunity.block_number
+            );
+            let payload = serde_json::to_string(&opportunity)?;
+            mq_client
+                .produce_record(&app_cfg.nats.subject_output, payload)
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn pair_metadata(kv: &Store, event: &SyncEvent) -> Result<Option<Pair>> {
+    match kv.get(event.pair.to_string()).await? {
+        Some(value) => Ok(Some(serde_json::from_slice(&value)?)),
+        None => {
+            debug!("pair {} not found in kv store", event.pair);
+            Ok(None)
+        }
+    }
+}
@@ -0,0 +1,153 @@
+use eyre::{eyre, Result};
+use std::collections::BTreeMap;
+use tracing::warn;
+
+use chain_model::{interval, Candle, PriceTick};
+
+use crate::init::CandlesConfig;
+
+struct Interval {
+    label: String,
+    secs: u64,
+    /// Candles still open, keyed on `(open_time, pair_address)`.
+    open: BTreeMap<(u64, String), Candle>,
+}
+
+/// Per-pair candles for every configured interval.
+///
+/// Time only advances with block timestamps: a candle closes once the latest
+/// tick seen on any pair is `grace_secs` past its end, so ticks arriving late
+/// or out of order are still merged by block number until then.
+pub struct Aggregator {
+    intervals: Vec<Interval>,
+    grace_secs: u64,
+    watermark: u64,
+}
+
+impl Aggregator {
+    pub fn new(cfg: &CandlesConfig) -> Result<Self> {
+        let intervals = cfg
+            .intervals
+            .iter()
+            .map(|label| {
+                Ok(Interval {
+                    label: label.clone(),
+                    secs: interval::parse_secs(label)
+                        .ok_or_else(|| eyre!("invalid candle interval '{label}'"))?,
+                    open: BTreeMap::new(),
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            intervals,
+            grace_secs: cfg.grace_secs,
+            watermark: 0,
+        })
+    }
+
+    /// Merges `tick` into its candles and returns them as updated. Candles that
+    /// were already closed are left untouched.
+    pub fn apply(&mut self, tick: &PriceTick) -> Vec<Candle> {
+        self.watermark = self.watermark.max(tick.block_timestamp);
+
+        let mut updated = Vec::new();
+        for interval in &mut self.intervals {
+            let open_time = tick.block_timestamp - tick.block_timestamp % interval.secs;
+            let close_time = open_time + interval.secs;
+            if close_time + self.grace_secs <= self.watermark {
+                warn!(
+                    "late tick {} of pair {} at {}, {} candle already closed",
+                    tick.transaction_hash, tick.pair_address, tick.block_timestamp, interval.label
+                );
+                continue;
+            }
+
+            let candle = interval
+                .open
+                .entry((open_time, tick.pair_address.clone()))
+                .and_modify(|candle| merge(candle, tick))
+                .or_insert_with(|| Candle {
+                    pair_address: tick.pair_address.clone(),
+                    token0_symbol: tick.token0_symbol.clone(),
+                    token1_symbol: tick.token1_symbol.clone(),
+                    interval: interval.label.clone(),
+                    open_time,
+                    close_time,
+                    open: tick.token0_token1,
+                    high: tick.token0_token1,
+                    low: tick.token0_token1,
+                    close: tick.token0_token1,
+                    tick_count: 1,
+                    token0_reserve: tick.token0_reserve,
+                    token1_reserve: tick.token1_reserve,
+                    liquidity_usd: tick.liquidity_usd,
+                    first_block: tick.block_number,
+                    last_block: tick.block_number,
+                    closed: false,
+                });
+            updated.push(candle.clone());
+        }
+        updated
+    }
+
+    /// Puts back an open candle stored before a restart. Returns `false` if
+    /// its interval is no longer configured.
+    pub fn restore(&mut self, candle: Candle) -> bool {
+        let Some(interval) = self
+            .intervals
+            .iter_mut()
+            .find(|interval| interval.label == candle.interval)
+        else {
+            return false;
+        };
+        interval
+            .open
+            .insert((candle.open_time, candle.pair_address.clone()), candle);
+        true
+    }
+
+    pub fn restore_watermark(&mut self, watermark: u64) {
+        self.watermark = self.watermark.max(watermark);
+    }
+
+    pub fn watermark(&self) -> u64 {
+        self.watermark
+    }
+
+    /// Removes and returns every candle whose grace window has passed.
+    pub fn close_due(&mut self) -> Vec<Candle> {
+        let mut closed = Vec::new();
+        for interval in &mut self.intervals {
+            while let Some(entry) = interval.open.first_entry() {
+                let (open_time, _) = *entry.key();
+                if open_time + interval.secs + self.grace_secs > self.watermark {
+                    break;
+                }
+                let mut candle = entry.remove();
+                candle.closed = true;
+                closed.push(candle);
+            }
+        }
+        closed
+    }
+}
+
+fn merge(candle: &mut Candle, tick: &PriceTick) {
+    let price = tick.token0_token1;
+    candle.high = candle.high.max(price);
+    candle.low = candle.low.min(price);
+    candle.tick_count += 1;
+
+    if tick.block_number < candle.first_block {
+        candle.open = price;
+        candle.first_block = tick.block_number;
+    }
+    // Ticks of one block arrive in log order, so the later one closes.
+    if tick.block_number >= candle.last_block {
+        candle.close = price;
+        candle.token0_reserve = tick.token0_reserve;
+        candle.token1_reserve = tick.token1_reserve;
+        candle.liquidity_usd = tick.liquidity_usd;
+        candle.last_block = tick.block_number;
+    }
+}
@@ -0,0 +1,114 @@
+use clap::Parser;
+use config::{Config, File};
+use eyre::Result;
+use serde::Deserialize;
+use std::path::Path;
+use tracing::debug;
+use tracing_subscriber::{fmt, prelude::*, EnvFilter};
+
+#[derive(Debug, Deserialize)]
+pub struct AppConfig {
+    pub nats: NatsConfig,
+    pub candles: CandlesConfig,
+    pub log: Option<LogConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NatsConfig {
+    pub server_url: String,
+    pub subject_input: String,
+    pub stream_name: String,
+    /// Closed candles go to `<subject_closed>.<interval>`.
+    pub subject_closed: String,
+    /// In-progress updates go to `<subject_updates>.<interval>`.
+    pub subject_updates: String,
+    /// KV bucket holding the open candles across restarts.
+    pub state_bucket: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CandlesConfig {
+    /// Interval labels such as `1m`, `5m`, `1h` or `1d`.
+    pub intervals: Vec<String>,
+    /// How long, in block time, a candle stays open for late ticks after its
+    /// interval ended.
+    pub grace_secs: u64,
+    /// Also publish the candle after every tick it absorbs.
+    pub emit_updates: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LogConfig {
+    pub level: String,
+}
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Cli {
+    #[arg(long)]
+    server_url: Option<String>,
+    #[arg(long)]
+    subject_input: Option<String>,
+    #[arg(long)]
+    stream_name: Option<String>,
+    #[arg(long)]
+    subject_closed: Option<String>,
+    #[arg(long)]
+    subject_updates: Option<String>,
+    #[arg(long)]
+    state_bucket: Option<String>,
+    #[arg(long)]
+    interval: Option<Vec<String>>,
+    #[arg(long)]
+    grace_secs: Option<u64>,
+    #[arg(long)]
+    emit_updates: Option<bool>,
+}
+
+impl AppConfig {
+    pub fn from_file_or_cli() -> Result<Self> {
+        let cli = Cli::parse();
+        let config_path1 = Path::new("config/candle-aggregator.toml");
+        let config_path2 = Path::new("candle-aggregator.toml");
+
+        let cfg: AppConfig = Config::builder()
+            .set_default("candles.intervals", vec!["1m", "5m", "1h", "1d"])?
+            .set_default("candles.grace_secs", 30)?
+            .set_default("candles.emit_updates", false)?
+            .add_source(File::from(config_path1).required(false))
+            .add_source(File::from(config_path2).required(false))
+            .set_override_option("nats.server_url", cli.server_url)?
+            .set_override_option("nats.subject_input", cli.subject_input)?
+            .set_override_option("nats.stream_name", cli.stream_name)?
+            .set_override_option("nats.subject_closed", cli.subject_closed)?
+            .set_override_option("nats.subject_updates", cli.subject_updates)?
+            .set_override_option("nats.state_bucket", cli.state_bucket)?
+            .set_override_option("candles.intervals", cli.interval)?
+            .set_override_option("candles.grace_secs", cli.grace_secs)?
+            .set_override_option("candles.emit_updates", cli.emit_updates)?
+            .build()
+            .map_err(eyre::Report::from)?
+            .try_deserialize()?;
+        Ok(cfg)
+    }
+
+    pub fn init_log(&self) -> Result<()> {
+        let level_str = self
+            .log
+            .as_ref()
+            .map(|l| l.level.as_str())
+            .unwrap_or("info");
+
+        let filter = EnvFilter::try_from_default_env()
+            .or_else(|_| EnvFilter::try_new(level_str))
+            .map_err(eyre::Report::from)?;
+
+        tracing_subscriber::registry()
+            .with(fmt::layer())
+            .with(filter)
+            .init();
+
+        debug!("log level configured to: '{}'", level_str);
+        Ok(())
+    }
+}
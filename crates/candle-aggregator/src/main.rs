@@ -0,0 +1,93 @@
+use eyre::{eyre, Result};
+use futures_util::StreamExt;
+use tracing::{debug, info, warn};
+
+use chain_model::{Candle, PriceTick};
+
+mod candle;
+mod init;
+mod mq;
+mod state;
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let app_cfg = init::AppConfig::from_file_or_cli()?;
+    app_cfg.init_log()?;
+    info!("starting candle-aggregator with config: {app_cfg:#?}");
+
+    let mq_client = mq::MqClient::new(
+        &app_cfg.nats.server_url,
+        &app_cfg.nats.subject_input,
+        &app_cfg.nats.stream_name,
+    )
+    .await?;
+
+    let mut aggregator = candle::Aggregator::new(&app_cfg.candles)?;
+    let state = match &app_cfg.nats.state_bucket {
+        Some(bucket) => Some(state::CandleState::new(mq_client.kv_store(bucket).await?)),
+        None => {
+            warn!("no nats.state_bucket, open candles are lost on restart");
+            None
+        }
+    };
+    let applied = match &state {
+        Some(state) => state.load(&mut aggregator).await?,
+        None => 0,
+    };
+
+    let mut sub = mq_client.jetstream_pull_from(true).await?;
+    while let Some(msg_result) = sub.next().await {
+        let msg = msg_result?;
+        let stream_sequence = msg
+            .info()
+            .map_err(|e| eyre!("message info failed: {e}"))?
+            .stream_sequence;
+        // Redelivered after the restart but already in the stored candles.
+        if stream_sequence <= applied {
+            msg.ack()
+                .await
+                .map_err(|e| eyre!("ack message failed: {e}"))?;
+            continue;
+        }
+
+        match serde_json::from_slice::<PriceTick>(&msg.payload) {
+            Ok(tick) => {
+                let updated = aggregator.apply(&tick);
+                if let Some(state) = &state {
+                    state
+                        .save(&updated, stream_sequence, aggregator.watermark())
+                        .await?;
+                }
+                if app_cfg.candles.emit_updates {
+                    for candle in &updated {
+                        publish(&mq_client, &app_cfg.nats.subject_updates, candle).await?;
+                    }
+                }
+                for candle in aggregator.close_due() {
+                    info!(
+                        "closed {} candle of {} at {}: {} ticks",
+                        candle.interval, candle.pair_address, candle.open_time, candle.tick_count
+                    );
+                    publish(&mq_client, &app_cfg.nats.subject_closed, &candle).await?;
+                    if let Some(state) = &state {
+                        state.remove(&candle).await?;
+                    }
+                }
+            }
+            Err(e) => warn!("invalid payload: {e}"),
+        }
+        msg.ack()
+            .await
+            .map_err(|e| eyre!("ack message failed: {e}"))?;
+    }
+
+    Ok(())
+}
+
+async fn publish(mq_client: &mq::MqClient, subject: &str, candle: &Candle) -> Result<()> {
+    debug!("candle: {candle:?}");
+    let payload = serde_json::to_string(candle)?;
+    mq_client
+        .produce_record(format!("{subject}.{}", candle.interval), payload)
+        .await
+}
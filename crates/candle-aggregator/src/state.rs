@@ -0,0 +1,112 @@
+use async_nats::jetstream::kv::{Operation, Store};
+use eyre::{eyre, Result};
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use chain_model::Candle;
+
+use crate::candle::Aggregator;
+
+/// Key of the input position; candle keys always contain dots.
+const CURSOR_KEY: &str = "cursor";
+
+/// How far the input has been applied to the stored candles.
+#[derive(Serialize, Deserialize, Default)]
+struct Cursor {
+    stream_sequence: u64,
+    watermark: u64,
+}
+
+/// Open candles and the input position in a KV bucket. Ticks are acked once
+/// their candles are stored here, so a restart resumes the open candles
+/// instead of closing truncated ones.
+pub struct CandleState {
+    kv: Store,
+}
+
+impl CandleState {
+    pub fn new(kv: Store) -> Self {
+        Self { kv }
+    }
+
+    /// Restores the stored candles into `aggregator` and returns the last
+    /// stream sequence they include.
+    pub async fn load(&self, aggregator: &mut Aggregator) -> Result<u64> {
+        let mut keys = Vec::new();
+        let mut stream = self.kv.keys().await?;
+        while let Some(key) = stream.next().await {
+            keys.push(key?);
+        }
+
+        let mut cursor = Cursor::default();
+        let mut restored = 0;
+        for key in keys {
+            let Some(entry) = self.kv.entry(&key).await? else {
+                continue;
+            };
+            if entry.operation != Operation::Put {
+                continue;
+            }
+            if key == CURSOR_KEY {
+                cursor = serde_json::from_slice(&entry.value)?;
+                continue;
+            }
+            match serde_json::from_slice::<Candle>(&entry.value) {
+                Ok(candle) => {
+                    if aggregator.restore(candle) {
+                        restored += 1;
+                    }
+                }
+                Err(e) => warn!("invalid candle state '{key}': {e}"),
+            }
+        }
+        aggregator.restore_watermark(cursor.watermark);
+        info!(
+            "restored {restored} open candles up to stream sequence {}",
+            cursor.stream_sequence
+        );
+        Ok(cursor.stream_sequence)
+    }
+
+    /// Stores the candles a tick changed, then the position it was read at.
+    pub async fn save(
+        &self,
+        candles: &[Candle],
+        stream_sequence: u64,
+        watermark: u64,
+    ) -> Result<()> {
+        for candle in candles {
+            let value = serde_json::to_vec(candle)?;
+            self.kv
+                .put(key(candle), value.into())
+                .await
+                .map_err(|e| eyre!("candle state put failed: {e}"))?;
+        }
+        let cursor = serde_json::to_vec(&Cursor {
+            stream_sequence,
+            watermark,
+        })?;
+        self.kv
+            .put(CURSOR_KEY, cursor.into())
+            .await
+            .map_err(|e| eyre!("candle cursor put failed: {e}"))?;
+        Ok(())
+    }
+
+    /// Forgets a candle once it was published as closed.
+    pub async fn remove(&self, candle: &Candle) -> Result<()> {
+        self.kv
+            .purge(key(candle))
+            .await
+            .map_err(|e| eyre!("candle state purge failed: {e}"))?;
+        Ok(())
+    }
+}
+
+fn key(candle: &Candle) -> String {
+    format!(
+        "{}.{}.{}",
+        candle.interval, candle.open_time, candle.pair_address
+    )
+}
@@ -0,0 +1,128 @@
+//! Constant-product math of `UniswapV2Library`, with the fee in basis points
+//! so forks with other fees fit too (30 bps is Uniswap V2's 0.3%).
+
+use alloy::primitives::U256;
+
+const BPS: u64 = 10_000;
+
+/// Output amount for `amount_in`, like `getAmountOut`. `None` without
+/// liquidity or on overflow.
+pub fn get_amount_out(
+    amount_in: U256,
+    reserve_in: U256,
+    reserve_out: U256,
+    fee_bps: u32,
+) -> Option<U256> {
+    if amount_in.is_zero() || reserve_in.is_zero() || reserve_out.is_zero() {
+        return None;
+    }
+    let amount_in_with_fee = amount_in.checked_mul(U256::from(BPS.checked_sub(fee_bps as u64)?))?;
+    let numerator = amount_in_with_fee.checked_mul(reserve_out)?;
+    let denominator = reserve_in
+        .checked_mul(U256::from(BPS))?
+        .checked_add(amount_in_with_fee)?;
+    Some(numerator / denominator)
+}
+
+/// Input amount needed for `amount_out`, like `getAmountIn`. `None` without
+/// liquidity, when `amount_out` drains the reserve or on overflow.
+pub fn get_amount_in(
+    amount_out: U256,
+    reserve_in: U256,
+    reserve_out: U256,
+    fee_bps: u32,
+) -> Option<U256> {
+    if amount_out.is_zero() || reserve_in.is_zero() || amount_out >= reserve_out {
+        return None;
+    }
+    let numerator = reserve_in
+        .checked_mul(amount_out)?
+        .checked_mul(U256::from(BPS))?;
+    let denominator =
+        (reserve_out - amount_out).checked_mul(U256::from(BPS.checked_sub(fee_bps as u64)?))?;
+    if denominator.is_zero() {
+        return None;
+    }
+    Some(numerator / denominator + U256::from(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ether(amount: u64) -> U256 {
+        U256::from(amount) * U256::from(10).pow(U256::from(18))
+    }
+
+    /// `(amount_in, reserve_in, reserve_out, amount_out)` of the swap test
+    /// cases of `UniswapV2Pair`, amounts in whole 18-decimal tokens.
+    const PAIR_SWAP_CASES: [(u64, u64, u64, u128); 7] = [
+        (1, 5, 10, 1_662_497_915_624_478_906),
+        (1, 10, 5, 453_305_446_940_074_565),
+        (2, 5, 10, 2_851_015_155_847_869_602),
+        (2, 10, 5, 831_248_957_812_239_453),
+        (1, 10, 10, 906_610_893_880_149_131),
+        (1, 100, 100, 987_158_034_397_061_298),
+        (1, 1000, 1000, 996_006_981_039_903_216),
+    ];
+
+    #[test]
+    fn amount_out_matches_uniswap_pair_cases() {
+        for (amount_in, reserve_in, reserve_out, expected) in PAIR_SWAP_CASES {
+            assert_eq!(
+                get_amount_out(ether(amount_in), ether(reserve_in), ether(reserve_out), 30),
+                Some(U256::from(expected)),
+                "{amount_in} in at {reserve_in}/{reserve_out}"
+            );
+        }
+    }
+
+    #[test]
+    fn amount_in_inverts_amount_out() {
+        for (amount_in, reserve_in, reserve_out, amount_out) in PAIR_SWAP_CASES {
+            let (reserve_in, reserve_out) = (ether(reserve_in), ether(reserve_out));
+            let needed =
+                get_amount_in(U256::from(amount_out), reserve_in, reserve_out, 30).unwrap();
+            // Rounded up, so never more than the input that produced it.
+            assert!(needed <= ether(amount_in));
+            assert!(
+                get_amount_out(needed, reserve_in, reserve_out, 30).unwrap()
+                    >= U256::from(amount_out)
+            );
+        }
+    }
+
+    #[test]
+    fn router_library_cases() {
+        let hundred = U256::from(100);
+        assert_eq!(
+            get_amount_out(U256::from(2), hundred, hundred, 30),
+            Some(U256::from(1))
+        );
+        assert_eq!(
+            get_amount_in(U256::from(1), hundred, hundred, 30),
+            Some(U256::from(2))
+        );
+    }
+
+    #[test]
+    fn no_quote_without_liquidity() {
+        let one = U256::from(1);
+        assert_eq!(get_amount_out(U256::ZERO, one, one, 30), None);
+        assert_eq!(get_amount_out(one, U256::ZERO, one, 30), None);
+        assert_eq!(get_amount_in(one, one, one, 30), None);
+        assert_eq!(get_amount_in(one, U256::ZERO, one, 30), None);
+        assert_eq!(get_amount_out(one, one, one, 10_001), None);
+    }
+
+    #[test]
+    fn fee_is_taken_from_the_input() {
+        let (reserve_in, reserve_out) = (ether(100), ether(100));
+        let uniswap = get_amount_out(ether(1), reserve_in, reserve_out, 30).unwrap();
+        let cheaper = get_amount_out(ether(1), reserve_in, reserve_out, 25).unwrap();
+        let free = get_amount_out(ether(1), reserve_in, reserve_out, 0).unwrap();
+        assert!(uniswap < cheaper && cheaper < free);
+        // x * y = k without a fee: 1 * 100 / (100 + 1), rounded down.
+        assert_eq!(free, ether(1) * ether(100) / ether(101));
+    }
+}
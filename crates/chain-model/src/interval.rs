@@ -0,0 +1,20 @@
+//! Interval labels such as `1m` or `1d`, shared by the services that bucket
+//! block time.
+
+/// Parses an interval label such as `30s`, `5m`, `1h` or `1d` into seconds.
+/// Returns `None` for an unknown unit, a zero count or an overflow.
+pub fn parse_secs(label: &str) -> Option<u64> {
+    let unit = label.chars().last()?;
+    let count: u64 = label.strip_suffix(unit)?.parse().ok()?;
+    let unit_secs = match unit {
+        's' => 1,
+        'm' => 60,
+        'h' => 3_600,
+        'd' => 86_400,
+        _ => return None,
+    };
+    if count == 0 {
+        return None;
+    }
+    count.checked_mul(unit_secs)
+}
@@ -1,12 +1,18 @@
 use alloy::primitives::{Address, FixedBytes, Uint, U256};
 use serde::{Deserialize, Serialize};
 
+pub mod amm;
+pub mod interval;
+
 /// Decoded `PairCreated` event data.
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct PairCreatedEvent {
     pub pair: Address,
     pub token0: Address,
     pub token1: Address,
+    /// Factory that emitted the event, absent from older producers.
+    #[serde(default)]
+    pub factory: Option<Address>,
     pub transaction_hash: FixedBytes<32>,
     pub block_number: u64,
     pub block_timestamp: u64,
@@ -19,6 +25,28 @@ pub struct SyncEvent {
     pub reserve0: Uint<112, 2>,
     pub reserve1: Uint<112, 2>,
     pub transaction_hash: FixedBytes<32>,
+    /// Position of the log in its block, absent from older producers.
+    #[serde(default)]
+    pub log_index: Option<u64>,
+    pub block_number: u64,
+    pub block_timestamp: u64,
+    /// Read via `getReserves()` at `block_number` rather than decoded from a
+    /// log; `transaction_hash` is zero and `log_index` is 0.
+    #[serde(default)]
+    pub snapshot: bool,
+}
+
+/// Decoded `Swap` event data.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SwapEvent {
+    pub pair: Address,
+    pub sender: Address,
+    pub to: Address,
+    pub amount0_in: U256,
+    pub amount1_in: U256,
+    pub amount0_out: U256,
+    pub amount1_out: U256,
+    pub transaction_hash: FixedBytes<32>,
     pub block_number: u64,
     pub block_timestamp: u64,
 }
@@ -30,6 +58,64 @@ pub struct Token {
     pub decimals: u8,
     pub symbol: String,
     pub total_supply: U256,
+    /// Listed in one of the curated token lists loaded by `pair-enricher`.
+    #[serde(default)]
+    pub verified: bool,
+    /// Heuristic risk checks, absent for verified tokens or when disabled.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub risk: Option<TokenRisk>,
+}
+
+/// Signals gathered by `pair-enricher`'s risk analysis of a token.
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct TokenRisk {
+    pub has_code: bool,
+    /// An EIP-1967 implementation or beacon slot is set.
+    pub is_proxy: bool,
+    /// `owner()` of the token, if it exposes one.
+    pub owner: Option<Address>,
+    /// Share of the total supply held by `owner`, in basis points.
+    pub owner_supply_bps: Option<u32>,
+    /// Amount withheld from a simulated transfer out of the pair (a buy), in
+    /// basis points.
+    pub transfer_fee_bps: Option<u32>,
+    /// Amount withheld when the bought tokens are transferred back into the
+    /// pair (a sell), in basis points.
+    #[serde(default)]
+    pub sell_fee_bps: Option<u32>,
+    pub flags: Vec<RiskFlag>,
+    /// Some checks failed to run; the fields above cover only the others.
+    #[serde(default)]
+    pub incomplete: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RiskFlag {
+    NoCode,
+    Proxy,
+    OwnerNotRenounced,
+    ConcentratedSupply,
+    TransferFee,
+    TransferReverts,
+    SellFee,
+    SellReverts,
+}
+
+impl RiskFlag {
+    /// The serialized name, e.g. `transfer_fee`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RiskFlag::NoCode => "no_code",
+            RiskFlag::Proxy => "proxy",
+            RiskFlag::OwnerNotRenounced => "owner_not_renounced",
+            RiskFlag::ConcentratedSupply => "concentrated_supply",
+            RiskFlag::TransferFee => "transfer_fee",
+            RiskFlag::TransferReverts => "transfer_reverts",
+            RiskFlag::SellFee => "sell_fee",
+            RiskFlag::SellReverts => "sell_reverts",
+        }
+    }
 }
 
 /// Represents a Uniswap Pair with its two tokens.
@@ -38,6 +124,12 @@ pub struct Pair {
     pub address: Address,
     pub token0: Token,
     pub token1: Token,
+    /// Factory that created the pair, when known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub factory: Option<Address>,
+    /// Block of the `PairCreated` event; unknown for factory scans.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_block: Option<u64>,
 }
 
 /// The final price data point to be stored or further processed.
@@ -53,10 +145,285 @@ pub struct PriceTick {
     pub token1_reserve: Uint<112, 2>,
     pub token1_symbol: String,
 
+    #[serde(default)]
+    pub token0_verified: bool,
+    #[serde(default)]
+    pub token1_verified: bool,
+
+    /// Price of one token0 in token1, lossy convenience view of the exact value.
     pub token0_token1: f64,
+    /// Price of one token1 in token0, lossy convenience view of the exact value.
     pub token1_token0: f64,
+    /// Exact fixed-point decimal price of one token0 in token1.
+    #[serde(default)]
+    pub token0_token1_exact: String,
+    /// Exact fixed-point decimal price of one token1 in token0.
+    #[serde(default)]
+    pub token1_token0_exact: String,
+
+    /// The pair oriented by the quote preference of `price-injector`:
+    /// `price` is one base token in quote tokens, e.g. PEPE in WETH.
+    #[serde(default)]
+    pub base_address: String,
+    #[serde(default)]
+    pub base_symbol: String,
+    #[serde(default)]
+    pub quote_address: String,
+    #[serde(default)]
+    pub quote_symbol: String,
+    #[serde(default)]
+    pub price: f64,
+    #[serde(default)]
+    pub price_exact: String,
+
+    /// USD price of one token0, when it can be routed to a stablecoin.
+    #[serde(default)]
+    pub token0_usd: Option<f64>,
+    #[serde(default)]
+    pub token1_usd: Option<f64>,
+    /// Value of both reserves in USD.
+    #[serde(default)]
+    pub liquidity_usd: Option<f64>,
+    /// Pairs the USD price was derived through, e.g. `PEPE/WETH>WETH/USDC`.
+    #[serde(default)]
+    pub usd_route: Option<String>,
 
     pub transaction_hash: String,
+    /// Log index of the `Sync` event, part of the tick's natural key.
+    #[serde(default)]
+    pub log_index: Option<u64>,
+    pub block_number: u64,
+    pub block_timestamp: u64,
+}
+
+/// Why `price-injector` held back a tick.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FilterReason {
+    EmptyReserve,
+    Unverified,
+    BlockedToken,
+    NotAllowedToken,
+    LowReserve,
+    LowLiquidityUsd,
+    Outlier,
+}
+
+/// A tick that did not pass the filters of `price-injector`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FilteredTick {
+    pub reason: FilterReason,
+    /// Human-readable specifics, e.g. the measured deviation.
+    pub detail: String,
+    pub tick: PriceTick,
+}
+
+/// Price of one token in the numeraire chosen in `price-injector`, derived
+/// over the most liquid path of tracked pairs.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TokenPrice {
+    pub token_address: String,
+    pub token_symbol: String,
+    pub numeraire_address: String,
+
+    pub price: f64,
+    /// Liquidity of the thinnest pair on the path, in numeraire units.
+    pub liquidity: f64,
+    /// Pair addresses from the token to the numeraire.
+    pub path: Vec<String>,
+
+    pub block_number: u64,
+    pub block_timestamp: u64,
+}
+
+/// OHLC of a pair's `token0_token1` price over one interval, keyed on block
+/// timestamps.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Candle {
+    pub pair_address: String,
+    pub token0_symbol: String,
+    pub token1_symbol: String,
+
+    /// Interval label as configured, e.g. `1m` or `1d`.
+    pub interval: String,
+    /// Inclusive start of the interval, unix seconds.
+    pub open_time: u64,
+    /// Exclusive end of the interval, unix seconds.
+    pub close_time: u64,
+
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub tick_count: u64,
+
+    /// Reserves of the closing tick.
+    pub token0_reserve: Uint<112, 2>,
+    pub token1_reserve: Uint<112, 2>,
+    /// Liquidity of the closing tick in USD, when it could be priced.
+    pub liquidity_usd: Option<f64>,
+
+    pub first_block: u64,
+    pub last_block: u64,
+    /// `false` for in-progress updates, `true` once the grace window passed.
+    pub closed: bool,
+}
+
+/// Time- and volume-weighted average prices of a pair over one window,
+/// published by `twap-oracle`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AveragePrice {
+    pub pair_address: String,
+    pub token0_symbol: String,
+    pub token1_symbol: String,
+
+    /// Window label as configured, e.g. `30m`.
+    pub window: String,
+    /// Seconds actually covered, shorter than the window while history builds up.
+    pub elapsed: u64,
+
+    /// TWAP of one token0 in token1, from the cumulative price accumulator.
+    pub token0_twap: f64,
+    /// TWAP of one token1 in token0.
+    pub token1_twap: f64,
+    /// VWAP of one token0 in token1 over the swaps in the window.
+    pub token0_vwap: Option<f64>,
+    /// Swapped token0 volume in the window, in whole tokens.
+    pub token0_volume: f64,
+    pub token1_volume: f64,
+
+    pub block_number: u64,
+    pub block_timestamp: u64,
+}
+
+/// Rule kinds of `price-alert`.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertKind {
+    PriceMove,
+    LiquidityDrop,
+    LiquidityBelow,
+    NewPairLiquidity,
+}
+
+impl AlertKind {
+    /// The serialized name, e.g. `price_move`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AlertKind::PriceMove => "price_move",
+            AlertKind::LiquidityDrop => "liquidity_drop",
+            AlertKind::LiquidityBelow => "liquidity_below",
+            AlertKind::NewPairLiquidity => "new_pair_liquidity",
+        }
+    }
+}
+
+/// A rule of `price-alert` that fired on a `PriceTick`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Alert {
+    /// Name of the rule as configured.
+    pub rule: String,
+    pub kind: AlertKind,
+
+    pub pair_address: String,
+    pub token0_symbol: String,
+    pub token1_symbol: String,
+
+    /// Observed value, e.g. the price change in percent or liquidity in USD.
+    pub value: f64,
+    /// Threshold of the rule the value crossed.
+    pub threshold: f64,
+    pub message: String,
+
+    pub transaction_hash: String,
+    pub block_number: u64,
+    pub block_timestamp: u64,
+}
+
+/// Request to `quote-service`. Exactly one of `amount_in` and `amount_out`
+/// is set, in whole tokens, e.g. `"1.5"`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct QuoteRequest {
+    pub pair: Address,
+    pub token_in: Address,
+    #[serde(default)]
+    pub amount_in: Option<String>,
+    #[serde(default)]
+    pub amount_out: Option<String>,
+}
+
+/// Swap quote of `quote-service` against the latest known reserves.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct QuoteResponse {
+    pub pair: Address,
+    pub token_in: Address,
+    pub token_out: Address,
+    pub symbol_in: String,
+    pub symbol_out: String,
+
+    /// Amounts in whole tokens, and raw as on chain.
+    pub amount_in: String,
+    pub amount_out: String,
+    pub amount_in_raw: U256,
+    pub amount_out_raw: U256,
+    pub fee_bps: u32,
+
+    /// Price of one token_in in token_out before the swap.
+    pub mid_price: f64,
+    /// Realized price of one token_in in token_out, fee included.
+    pub execution_price: f64,
+    /// Shortfall of the execution price against the mid price, in percent.
+    pub price_impact_percent: f64,
+
+    pub reserve_in: U256,
+    pub reserve_out: U256,
+    /// Block of the Sync event the reserves come from.
+    pub block_number: u64,
+}
+
+/// Reply of `quote-service` when a request cannot be quoted.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct QuoteError {
+    pub error: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ArbKind {
+    /// The same token pair on two venues.
+    CrossVenue,
+    /// Three pairs closing a cycle through a third token.
+    Triangular,
+}
+
+/// One swap of an arbitrage cycle.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ArbHop {
+    pub pair: Address,
+    pub token_in: Address,
+    pub token_out: Address,
+    pub amount_in: U256,
+    pub amount_out: U256,
+    pub fee_bps: u32,
+}
+
+/// A profitable cycle found by `arb-detector`, before gas.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ArbOpportunity {
+    pub kind: ArbKind,
+    /// Token the cycle starts and ends in; the profit is denominated in it.
+    pub token: Address,
+    pub symbol: String,
+    pub hops: Vec<ArbHop>,
+
+    pub amount_in: U256,
+    pub amount_out: U256,
+    pub gross_profit: U256,
+    /// `gross_profit` in whole tokens.
+    pub gross_profit_units: f64,
+
+    /// Pair whose Sync revealed the opportunity.
+    pub trigger_pair: Address,
     pub block_number: u64,
     pub block_timestamp: u64,
 }
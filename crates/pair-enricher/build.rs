@@ -0,0 +1,15 @@
+use std::path::Path;
+
+// Assembles the EVM programs in `probe/` into `OUT_DIR`.
+fn main() {
+    println!("cargo:rerun-if-changed=probe");
+    let out_dir = std::env::var("OUT_DIR").expect("OUT_DIR is set by cargo");
+    for name in ["transfer_probe", "test_token"] {
+        let mut code = Vec::new();
+        etk_asm::ingest::Ingest::new(&mut code)
+            .ingest_file(format!("probe/{name}.etk"))
+            .unwrap_or_else(|e| panic!("assembling probe/{name}.etk: {e}"));
+        std::fs::write(Path::new(&out_dir).join(format!("{name}.bin")), code)
+            .expect("writing assembled probe");
+    }
+}
@@ -0,0 +1,165 @@
+use alloy::primitives::Address;
+use async_nats::jetstream::kv::Store;
+use eyre::{eyre, Result, WrapErr};
+use futures_util::StreamExt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use tracing::{info, warn};
+
+use chain_model::Pair;
+
+use crate::enricher::Enricher;
+use crate::pair_erc20::EthReader;
+
+/// Fetches a pair from chain and stores it like a `PairCreated` event would.
+pub async fn add(eth_reader: &EthReader, enricher: &Enricher<'_>, pair: Address) -> Result<()> {
+    let fetched = eth_reader.fetch_pair(pair).await?;
+    match enricher.store(fetched).await? {
+        Some(stored) => info!(
+            "added pair {} {}/{}",
+            stored.address, stored.token0.symbol, stored.token1.symbol
+        ),
+        None => warn!("pair {pair} rejected"),
+    }
+    Ok(())
+}
+
+pub async fn remove(kv: &Store, pair: Address) -> Result<()> {
+    if kv.get(pair.to_string()).await?.is_none() {
+        return Err(eyre!("pair {pair} not found in kv store"));
+    }
+    kv.delete(pair.to_string()).await?;
+    info!("removed pair {pair}");
+    Ok(())
+}
+
+/// Re-fetches token metadata of `pair`, or of every pair in the bucket.
+pub async fn refresh(
+    eth_reader: &EthReader,
+    enricher: &Enricher<'_>,
+    kv: &Store,
+    pair: Option<Address>,
+) -> Result<()> {
+    let pairs = match pair {
+        Some(pair) => vec![pair],
+        None => pair_keys(kv).await?,
+    };
+    info!("refreshing {} pairs", pairs.len());
+
+    for pair in pairs {
+        match eth_reader.fetch_pair(pair).await {
+            Ok(mut fetched) => {
+                // Creation details cannot be read back from the pair contract.
+                if let Some(value) = kv.get(pair.to_string()).await? {
+                    if let Ok(stored) = serde_json::from_slice::<Pair>(&value) {
+                        fetched.factory = stored.factory;
+                        fetched.created_block = stored.created_block;
+                    }
+                }
+                // Stored pairs the pipeline now rejects, e.g. after a token
+                // list change, must not linger with stale metadata.
+                if enricher.store(fetched).await?.is_none() {
+                    kv.delete(pair.to_string()).await?;
+                    warn!("pair {pair} rejected, removed from kv store");
+                }
+            }
+            Err(e) => warn!("refresh of pair {pair} failed: {e}"),
+        }
+    }
+    Ok(())
+}
+
+/// Prints a table of every pair in the bucket.
+pub async fn list(kv: &Store) -> Result<()> {
+    let mut out = io::stdout().lock();
+    writeln!(
+        out,
+        "{:<42}  {:<12} {:<12} {:>4} {:>4} {:>9}",
+        "PAIR", "TOKEN0", "TOKEN1", "DEC0", "DEC1", "REVISION"
+    )?;
+    for key in pair_keys(kv).await? {
+        let Some(entry) = kv.entry(key.to_string()).await? else {
+            continue;
+        };
+        match serde_json::from_slice::<Pair>(&entry.value) {
+            Ok(pair) => writeln!(
+                out,
+                "{:<42}  {:<12} {:<12} {:>4} {:>4} {:>9}",
+                pair.address,
+                pair.token0.symbol,
+                pair.token1.symbol,
+                pair.token0.decimals,
+                pair.token1.decimals,
+                entry.revision
+            )?,
+            Err(e) => warn!("invalid pair {key}: {e}"),
+        }
+    }
+    Ok(())
+}
+
+/// Writes every pair as one JSON line to `output`, or stdout.
+pub async fn export(kv: &Store, output: Option<&str>) -> Result<()> {
+    let mut out: Box<dyn Write> = match output {
+        Some(path) => Box::new(BufWriter::new(
+            File::create(path).wrap_err_with(|| format!("create {path}"))?,
+        )),
+        None => Box::new(io::stdout().lock()),
+    };
+
+    let mut count = 0;
+    for key in pair_keys(kv).await? {
+        if let Some(value) = kv.get(key.to_string()).await? {
+            // Round-trip through `Pair` so the export only holds valid records.
+            match serde_json::from_slice::<Pair>(&value) {
+                Ok(pair) => {
+                    serde_json::to_writer(&mut out, &pair)?;
+                    writeln!(out)?;
+                    count += 1;
+                }
+                Err(e) => warn!("invalid pair {key}: {e}"),
+            }
+        }
+    }
+    out.flush()?;
+    info!("exported {count} pairs");
+    Ok(())
+}
+
+/// Stores every JSON line of `input`, or stdin, as is.
+pub async fn import(kv: &Store, input: Option<&str>) -> Result<()> {
+    let reader: Box<dyn BufRead> = match input {
+        Some(path) => Box::new(BufReader::new(
+            File::open(path).wrap_err_with(|| format!("open {path}"))?,
+        )),
+        None => Box::new(io::stdin().lock()),
+    };
+
+    let mut count = 0;
+    for (line_no, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let pair: Pair = serde_json::from_str(&line)
+            .wrap_err_with(|| format!("invalid pair on line {}", line_no + 1))?;
+        let value = serde_json::to_vec(&pair)?;
+        kv.put(pair.address.to_string(), value.into()).await?;
+        count += 1;
+    }
+    info!("imported {count} pairs");
+    Ok(())
+}
+
+async fn pair_keys(kv: &Store) -> Result<Vec<Address>> {
+    let mut pairs = Vec::new();
+    let mut keys = kv.keys().await?;
+    while let Some(key) = keys.next().await {
+        let key = key?;
+        match key.parse::<Address>() {
+            Ok(addr) => pairs.push(addr),
+            Err(e) => warn!("Failed to parse key '{key}' as address: {e}"),
+        }
+    }
+    Ok(pairs)
+}
@@ -0,0 +1,69 @@
+use async_nats::jetstream::kv::Store;
+use eyre::Result;
+use tracing::{info, warn};
+
+use chain_model::{Pair, SyncEvent};
+
+use crate::mq::MqClient;
+use crate::risk::RiskAnalyzer;
+use crate::snapshot::SnapshotPublisher;
+use crate::token_list::TokenList;
+
+/// The steps every freshly fetched pair goes through before it is tracked:
+/// token list curation, risk analysis, the KV write, the stored pair on
+/// `subject_output` and a reserve snapshot.
+pub struct Enricher<'a> {
+    pub token_list: &'a TokenList,
+    pub risk: Option<&'a RiskAnalyzer<'a>>,
+    pub snapshots: Option<&'a SnapshotPublisher<'a>>,
+    pub kv: &'a Store,
+    pub mq_client: &'a MqClient,
+    pub subject_output: &'a str,
+}
+
+impl Enricher<'_> {
+    /// Returns the stored pair, or `None` if the token list rejected it.
+    pub async fn store(&self, pair: Pair) -> Result<Option<Pair>> {
+        self.store_with_snapshot(pair, None).await
+    }
+
+    /// Like `store`, but publishes `snapshot` (reserves the caller already
+    /// read) instead of reading the pair again.
+    pub async fn store_with_snapshot(
+        &self,
+        pair: Pair,
+        snapshot: Option<SyncEvent>,
+    ) -> Result<Option<Pair>> {
+        let Some(mut pair) = self.token_list.curate(pair) else {
+            return Ok(None);
+        };
+        if let Some(risk) = self.risk {
+            risk.analyze_pair(&mut pair).await;
+        }
+
+        let value = serde_json::to_string(&pair)?;
+        self.kv
+            .put(pair.address.to_string(), value.clone().into())
+            .await?;
+        info!("put pair {} to kv store", pair.address);
+
+        if let Err(e) = self
+            .mq_client
+            .produce_record(self.subject_output, value)
+            .await
+        {
+            warn!("publishing pair {} failed: {}", pair.address, e);
+        }
+
+        if let Some(snapshots) = self.snapshots {
+            let published = match &snapshot {
+                Some(event) => snapshots.publish_event(event).await,
+                None => snapshots.publish(pair.address).await,
+            };
+            if let Err(e) = published {
+                warn!("snapshot of pair {} failed: {}", pair.address, e);
+            }
+        }
+        Ok(Some(pair))
+    }
+}
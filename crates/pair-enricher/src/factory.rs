@@ -0,0 +1,304 @@
+use alloy::primitives::{Address, FixedBytes, U256};
+use async_nats::jetstream::kv::Store;
+use eyre::{eyre, Result};
+use futures_util::{stream, StreamExt};
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+use std::str::FromStr;
+use std::sync::Mutex;
+use tracing::{info, warn};
+
+use chain_model::{Pair, SyncEvent, Token};
+
+use crate::enricher::Enricher;
+use crate::init::FactoryScanConfig;
+use crate::pair_erc20::{EthReader, PairInfo};
+
+#[derive(Debug, Default)]
+struct BatchScan {
+    stored: usize,
+    /// Pairs whose state or token metadata could not be read.
+    skipped: Vec<Address>,
+}
+
+/// Enumerates a UniswapV2 factory through `allPairs(i)` and stores every
+/// matching pair through the same [`Enricher`] as the event consumer.
+pub struct FactoryScanner<'a> {
+    eth_reader: &'a EthReader,
+    enricher: &'a Enricher<'a>,
+    factory: Address,
+    batch_size: u64,
+    concurrency: usize,
+    min_reserve: HashMap<Address, f64>,
+    token_allowlist: Option<HashSet<Address>>,
+    // Tokens such as WETH appear in most pairs, fetch them only once.
+    tokens: Mutex<HashMap<Address, Token>>,
+}
+
+impl<'a> FactoryScanner<'a> {
+    pub fn new(
+        eth_reader: &'a EthReader,
+        enricher: &'a Enricher<'a>,
+        factory: Address,
+        cfg: &FactoryScanConfig,
+    ) -> Result<Self> {
+        let token_allowlist = cfg
+            .token_allowlist
+            .as_ref()
+            .map(|list| list.iter().map(|a| Address::from_str(a)).collect())
+            .transpose()?;
+        let min_reserve = cfg
+            .min_reserve
+            .iter()
+            .map(|(token, units)| Ok((Address::from_str(token)?, *units)))
+            .collect::<Result<_>>()?;
+
+        Ok(Self {
+            eth_reader,
+            enricher,
+            factory,
+            batch_size: cfg.batch_size.max(1),
+            concurrency: cfg.concurrency.max(1),
+            min_reserve,
+            token_allowlist,
+            tokens: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Scans `[from_index, to_index)`. Without `from_index` the scan resumes at
+    /// the index stored in `cursor`, which is then advanced after every batch;
+    /// an explicit `from_index` leaves the cursor alone.
+    pub async fn run(
+        &self,
+        cursor: &Store,
+        from_index: Option<u64>,
+        to_index: Option<u64>,
+    ) -> Result<()> {
+        self.retry_skipped(cursor).await?;
+
+        let length = self.eth_reader.all_pairs_length(self.factory).await?;
+        let resume = from_index.is_none();
+        let start = match from_index {
+            Some(index) => index,
+            None => self.load_cursor(cursor).await?,
+        };
+        let end = to_index.map_or(length, |index| index.min(length));
+        info!(
+            "scanning factory {} pairs [{start}, {end}) of {length}",
+            self.factory
+        );
+
+        let batches = (start..end)
+            .step_by(self.batch_size as usize)
+            .map(|from| from..(from + self.batch_size).min(end));
+
+        // `buffered` keeps completion order, so the cursor only moves past
+        // batches that are fully stored.
+        let mut results = stream::iter(batches)
+            .map(|range| async move {
+                let scan = self.scan_batch(range.clone()).await;
+                (range, scan)
+            })
+            .buffered(self.concurrency);
+
+        let (mut total, mut total_skipped) = (0, 0);
+        while let Some((range, scan)) = results.next().await {
+            let scan = scan.map_err(|e| {
+                eyre!(
+                    "scan of [{}, {}) failed, resume from {}: {e}",
+                    range.start,
+                    range.end,
+                    range.start
+                )
+            })?;
+            // Recorded before the cursor moves past them.
+            for pair in &scan.skipped {
+                cursor
+                    .put(self.retry_key(*pair), "unreadable".into())
+                    .await?;
+            }
+            if resume {
+                cursor
+                    .put(self.factory.to_string(), range.end.to_string().into())
+                    .await?;
+            }
+            total += scan.stored;
+            total_skipped += scan.skipped.len();
+            info!(
+                "scanned pairs [{}, {}): stored {}, skipped {}, total {total}",
+                range.start,
+                range.end,
+                scan.stored,
+                scan.skipped.len()
+            );
+        }
+
+        info!("factory scan finished, stored {total} pairs, {total_skipped} skipped for retry");
+        Ok(())
+    }
+
+    /// Key in the cursor bucket marking `pair` for a retry.
+    fn retry_key(&self, pair: Address) -> String {
+        format!("{}.retry.{pair}", self.factory)
+    }
+
+    /// Scans again the pairs earlier runs could not read, and clears those
+    /// that now succeed.
+    async fn retry_skipped(&self, cursor: &Store) -> Result<()> {
+        let prefix = format!("{}.retry.", self.factory);
+        let mut pairs = Vec::new();
+        let mut keys = cursor.keys().await?;
+        while let Some(key) = keys.next().await {
+            if let Some(pair) = key?.strip_prefix(&prefix) {
+                pairs.push(Address::from_str(pair)?);
+            }
+        }
+        if pairs.is_empty() {
+            return Ok(());
+        }
+        info!("retrying {} previously skipped pairs", pairs.len());
+
+        for chunk in pairs.chunks(self.batch_size as usize) {
+            let scan = self.scan_pairs(chunk.to_vec()).await?;
+            for pair in chunk.iter().filter(|pair| !scan.skipped.contains(pair)) {
+                cursor.purge(self.retry_key(*pair)).await?;
+            }
+            if !scan.skipped.is_empty() {
+                warn!("{} pairs are still unreadable", scan.skipped.len());
+            }
+        }
+        Ok(())
+    }
+
+    async fn load_cursor(&self, cursor: &Store) -> Result<u64> {
+        match cursor.get(self.factory.to_string()).await? {
+            Some(value) => Ok(std::str::from_utf8(&value)?.parse()?),
+            None => Ok(0),
+        }
+    }
+
+    async fn scan_batch(&self, range: Range<u64>) -> Result<BatchScan> {
+        let pair_addresses = self.eth_reader.all_pairs(self.factory, range).await?;
+        self.scan_pairs(pair_addresses).await
+    }
+
+    async fn scan_pairs(&self, pair_addresses: Vec<Address>) -> Result<BatchScan> {
+        // Pin the reserve reads so they can double as snapshot `Sync` events.
+        let block_number = self.eth_reader.block_number().await?;
+        let fetched = self
+            .eth_reader
+            .fetch_pair_infos(&pair_addresses, block_number)
+            .await?;
+
+        let mut scan = BatchScan::default();
+        let mut infos = Vec::with_capacity(fetched.len());
+        for (address, info) in pair_addresses.iter().zip(fetched) {
+            match info {
+                Some(info) if self.is_allowed(&info) => infos.push(info),
+                Some(_) => {}
+                None => {
+                    warn!("skip pair {address}: pair state unavailable");
+                    scan.skipped.push(*address);
+                }
+            }
+        }
+
+        self.fetch_missing_tokens(&infos).await?;
+        let block_timestamp = match self.enricher.snapshots {
+            Some(_) => self.eth_reader.block_timestamp(block_number).await?,
+            None => 0,
+        };
+
+        for info in infos {
+            let Some(pair) = self.build_pair(&info) else {
+                warn!("skip pair {}: token metadata unavailable", info.address);
+                scan.skipped.push(info.address);
+                continue;
+            };
+            if !self.has_min_reserve(&info, &pair) {
+                continue;
+            }
+            let snapshot = self.enricher.snapshots.map(|_| SyncEvent {
+                pair: info.address,
+                reserve0: info.reserve0,
+                reserve1: info.reserve1,
+                transaction_hash: FixedBytes::ZERO,
+                log_index: Some(0),
+                block_number,
+                block_timestamp,
+                snapshot: true,
+            });
+            if self
+                .enricher
+                .store_with_snapshot(pair, snapshot)
+                .await?
+                .is_some()
+            {
+                scan.stored += 1;
+            }
+        }
+        Ok(scan)
+    }
+
+    async fn fetch_missing_tokens(&self, infos: &[PairInfo]) -> Result<()> {
+        let missing: Vec<Address> = {
+            let tokens = self.tokens.lock().unwrap();
+            let unique: HashSet<Address> = infos
+                .iter()
+                .flat_map(|info| [info.token0, info.token1])
+                .filter(|addr| !tokens.contains_key(addr))
+                .collect();
+            unique.into_iter().collect()
+        };
+        if missing.is_empty() {
+            return Ok(());
+        }
+
+        let fetched = self.eth_reader.fetch_tokens(&missing).await?;
+        let mut tokens = self.tokens.lock().unwrap();
+        for token in fetched.into_iter().flatten() {
+            tokens.insert(token.address, token);
+        }
+        Ok(())
+    }
+
+    fn is_allowed(&self, info: &PairInfo) -> bool {
+        match &self.token_allowlist {
+            Some(list) => list.contains(&info.token0) || list.contains(&info.token1),
+            None => true,
+        }
+    }
+
+    fn build_pair(&self, info: &PairInfo) -> Option<Pair> {
+        let tokens = self.tokens.lock().unwrap();
+        Some(Pair {
+            address: info.address,
+            token0: tokens.get(&info.token0)?.clone(),
+            token1: tokens.get(&info.token1)?.clone(),
+            factory: Some(self.factory),
+            created_block: None,
+        })
+    }
+
+    /// A whole-unit threshold means something else for every token, so only
+    /// sides with their own `min_reserve` are checked, and a pair needs one.
+    fn has_min_reserve(&self, info: &PairInfo, pair: &Pair) -> bool {
+        if self.min_reserve.is_empty() {
+            return true;
+        }
+        let mut checked = false;
+        for (reserve, token) in [(info.reserve0, &pair.token0), (info.reserve1, &pair.token1)] {
+            if let Some(min) = self.min_reserve.get(&token.address) {
+                if to_units(U256::from(reserve), token.decimals) < *min {
+                    return false;
+                }
+                checked = true;
+            }
+        }
+        checked
+    }
+}
+
+fn to_units(amount: U256, decimals: u8) -> f64 {
+    f64::from(amount) / 10f64.powi(decimals as i32)
+}
@@ -1,22 +1,35 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use config::{Config, File};
 use eyre::Result;
 use serde::Deserialize;
+use std::collections::HashMap;
 use std::path::Path;
 use tracing::debug;
 use tracing_subscriber::{fmt, prelude::*, EnvFilter};
 
+use crate::rate_limit::RateLimiter;
+
 #[derive(Debug, Deserialize)]
 pub struct AppConfig {
     pub eth_node: EthNodeConfig,
     pub nats: NatsConfig,
+    pub enricher: EnricherConfig,
     pub uniswap_v2: UniswapV2Config,
+    pub factory_scan: FactoryScanConfig,
+    pub token_list: Option<TokenListConfig>,
+    pub risk: RiskConfig,
     pub log: Option<LogConfig>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct EthNodeConfig {
     pub http_url: String,
+    /// Upper bound on RPC requests per second across all tasks.
+    pub max_rps: Option<u32>,
+    /// Requests allowed in a burst above `max_rps`.
+    pub rps_burst: Option<u32>,
+    /// Read token metadata at the `PairCreated` block (needs an archive node).
+    pub pin_block: bool,
 }
 
 #[derive(Debug, Deserialize)]
@@ -24,15 +37,63 @@ pub struct NatsConfig {
     pub server_url: String,
     pub subject_input: String,
     pub kv_bucket: String,
+    /// Every stored pair is published here after its KV write.
     pub subject_output: String,
     pub stream_name: String,
+    /// Subject for `getReserves()` snapshots of newly stored pairs.
+    pub subject_sync: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EnricherConfig {
+    /// `PairCreated` messages processed at once.
+    pub concurrency: usize,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct UniswapV2Config {
+    pub factory_address: Option<String>,
     pub pair_address: Option<Vec<String>>,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct FactoryScanConfig {
+    /// Number of `allPairs` indexes read per multicall batch.
+    pub batch_size: u64,
+    /// Number of batches in flight at once.
+    pub concurrency: usize,
+    /// KV bucket holding the next index to scan, keyed by factory address,
+    /// and `<factory>.retry.<pair>` marks for pairs that could not be read.
+    pub cursor_bucket: String,
+    /// Minimum reserve in whole units per token address. A pair is kept only
+    /// if at least one of its tokens is listed and every listed side holds
+    /// its minimum.
+    #[serde(default)]
+    pub min_reserve: HashMap<String, f64>,
+    /// Keep only pairs with at least one token in this list.
+    pub token_allowlist: Option<Vec<String>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TokenListConfig {
+    /// Local paths of Uniswap-format token list JSON files.
+    pub paths: Vec<String>,
+    /// Reject pairs unless both tokens are listed.
+    #[serde(default)]
+    pub require_listed: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RiskConfig {
+    /// Run the risk checks on unverified tokens of newly enriched pairs.
+    pub enabled: bool,
+    /// Owner share of total supply above which `ConcentratedSupply` is flagged.
+    pub max_owner_supply_bps: u32,
+    /// Simulated buy or sell fee above which `TransferFee` or `SellFee` is
+    /// flagged.
+    pub max_transfer_fee_bps: u32,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct LogConfig {
     pub level: String,
@@ -41,42 +102,173 @@ pub struct LogConfig {
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Cli {
-    #[arg(long)]
+    #[arg(long, global = true)]
     http_url: Option<String>,
-    #[arg(long)]
+    #[arg(long, global = true)]
+    max_rps: Option<u32>,
+    #[arg(long, global = true)]
+    pin_block: Option<bool>,
+    #[arg(long, global = true)]
     server_url: Option<String>,
-    #[arg(long)]
+    #[arg(long, global = true)]
     subject_input: Option<String>,
-    #[arg(long)]
+    #[arg(long, global = true)]
     subject_output: Option<String>,
-    #[arg(long)]
+    #[arg(long, global = true)]
+    subject_sync: Option<String>,
+    #[arg(long, global = true)]
     kv_bucket: Option<String>,
-    #[arg(long)]
+    #[arg(long, global = true)]
     stream_name: Option<String>,
-    #[arg(long)]
+    #[arg(long, global = true)]
     pair_address: Option<Vec<String>>,
+    #[arg(long, global = true)]
+    token_list: Option<Vec<String>>,
+    #[arg(long)]
+    enrich_concurrency: Option<usize>,
+    #[arg(long)]
+    analyze_risk: Option<bool>,
+    #[command(subcommand)]
+    command: Option<Commands>,
+}
+
+/// Without a subcommand the enricher consumes `PairCreated` events.
+#[derive(Subcommand, Debug, Clone)]
+pub enum Commands {
+    /// Walk the factory's `allPairs` index and enrich every pair into the KV bucket.
+    ScanFactory {
+        #[arg(long)]
+        factory_address: Option<String>,
+        /// First index to scan; defaults to the stored cursor, which is only
+        /// advanced when scanning from it.
+        #[arg(long)]
+        from_index: Option<u64>,
+        /// Index to stop at (exclusive); defaults to `allPairsLength()`.
+        #[arg(long)]
+        to_index: Option<u64>,
+        #[arg(long)]
+        batch_size: Option<u64>,
+        #[arg(long)]
+        concurrency: Option<usize>,
+        /// `TOKEN=UNITS`, repeatable.
+        #[arg(long, value_parser = parse_min_reserve)]
+        min_reserve: Vec<(String, f64)>,
+        #[arg(long)]
+        token_allowlist: Option<Vec<String>>,
+    },
+    #[command(flatten)]
+    Pipeline(PipelineCommand),
+    #[command(flatten)]
+    Kv(KvCommand),
+}
+
+/// Admin commands that run pairs through the same pipeline as the consumer.
+#[derive(Subcommand, Debug, Clone)]
+pub enum PipelineCommand {
+    /// Fetch a pair from chain and store it in the KV bucket.
+    Add { pair: String },
+    /// Re-fetch token metadata of one pair, or of every pair with `--all`.
+    Refresh {
+        #[arg(required_unless_present = "all", conflicts_with = "all")]
+        pair: Option<String>,
+        #[arg(long)]
+        all: bool,
+    },
+}
+
+/// Admin commands that only touch the KV bucket.
+#[derive(Subcommand, Debug, Clone)]
+pub enum KvCommand {
+    /// Delete a pair from the KV bucket.
+    Remove { pair: String },
+    /// Print a table of the pairs in the KV bucket.
+    List,
+    /// Write every pair as JSON lines to a file or stdout.
+    Export {
+        #[arg(long)]
+        output: Option<String>,
+    },
+    /// Store pairs from a JSON lines file or stdin.
+    Import {
+        #[arg(long)]
+        input: Option<String>,
+    },
+}
+
+/// Parses a `TOKEN=UNITS` value of `--min-reserve`.
+fn parse_min_reserve(arg: &str) -> std::result::Result<(String, f64), String> {
+    let (token, units) = arg.split_once('=').ok_or("expected TOKEN=UNITS")?;
+    let units = units.parse().map_err(|e| format!("{units}: {e}"))?;
+    Ok((token.to_string(), units))
 }
 
 impl AppConfig {
-    pub fn from_file_or_cli() -> Result<Self> {
+    pub fn from_file_or_cli() -> Result<(AppConfig, Option<Commands>)> {
         let cli = Cli::parse();
+        let cmd = cli.command.clone();
         let config_path1 = Path::new("config/pair-enricher.toml");
         let config_path2 = Path::new("pair-enricher.toml");
 
-        let cfg: AppConfig = Config::builder()
+        let mut builder = Config::builder()
+            .set_default("eth_node.pin_block", false)?
+            .set_default("enricher.concurrency", 8)?
+            .set_default("risk.enabled", false)?
+            .set_default("risk.max_owner_supply_bps", 2000)?
+            .set_default("risk.max_transfer_fee_bps", 0)?
+            .set_default("factory_scan.batch_size", 500)?
+            .set_default("factory_scan.concurrency", 4)?
+            .set_default("factory_scan.cursor_bucket", "univ2_factory_scan")?
             .add_source(File::from(config_path1).required(false))
             .add_source(File::from(config_path2).required(false))
             .set_override_option("eth_node.http_url", cli.http_url)?
+            .set_override_option("eth_node.max_rps", cli.max_rps)?
+            .set_override_option("eth_node.pin_block", cli.pin_block)?
             .set_override_option("nats.server_url", cli.server_url)?
             .set_override_option("nats.subject_input", cli.subject_input)?
             .set_override_option("nats.subject_output", cli.subject_output)?
+            .set_override_option("nats.subject_sync", cli.subject_sync)?
             .set_override_option("nats.kv_bucket", cli.kv_bucket)?
             .set_override_option("nats.stream_name", cli.stream_name)?
             .set_override_option("uniswap_v2.pair_address", cli.pair_address)?
+            .set_override_option("token_list.paths", cli.token_list)?
+            .set_override_option(
+                "enricher.concurrency",
+                cli.enrich_concurrency.map(|c| c as u64),
+            )?
+            .set_override_option("risk.enabled", cli.analyze_risk)?;
+
+        if let Some(Commands::ScanFactory {
+            factory_address,
+            batch_size,
+            concurrency,
+            min_reserve,
+            token_allowlist,
+            ..
+        }) = cli.command
+        {
+            builder = builder
+                .set_override_option("uniswap_v2.factory_address", factory_address)?
+                .set_override_option("factory_scan.batch_size", batch_size)?
+                .set_override_option("factory_scan.concurrency", concurrency.map(|c| c as u64))?
+                .set_override_option("factory_scan.token_allowlist", token_allowlist)?;
+            for (token, units) in min_reserve {
+                builder =
+                    builder.set_override(format!("factory_scan.min_reserve.{token}"), units)?;
+            }
+        }
+
+        let cfg: AppConfig = builder
             .build()
             .map_err(eyre::Report::from)?
             .try_deserialize()?;
-        Ok(cfg)
+        Ok((cfg, cmd))
+    }
+
+    pub fn rate_limiter(&self) -> Option<RateLimiter> {
+        self.eth_node.max_rps.map(|rps| {
+            let burst = self.eth_node.rps_burst.unwrap_or(rps);
+            RateLimiter::new(rps, burst)
+        })
     }
 
     pub fn init_log(&self) -> Result<()> {
@@ -90,8 +282,9 @@ impl AppConfig {
             .or_else(|_| EnvFilter::try_new(level_str))
             .map_err(eyre::Report::from)?;
 
+        // Logs go to stderr, stdout is reserved for `list` and `export` output.
         tracing_subscriber::registry()
-            .with(fmt::layer())
+            .with(fmt::layer().with_writer(std::io::stderr))
             .with(filter)
             .init();
 
@@ -1,22 +1,54 @@
 use alloy::primitives::Address;
-use eyre::Result;
+use async_nats::jetstream::AckKind;
+use eyre::{eyre, Result};
 use futures_util::StreamExt;
 use std::str::FromStr;
-use tracing::info;
+use std::time::Duration;
+use tracing::{info, warn};
 
 use chain_model::PairCreatedEvent;
+use init::{AppConfig, Commands, KvCommand, PipelineCommand};
 
+mod admin;
+mod enricher;
+mod factory;
 mod init;
 mod mq;
 mod pair_erc20;
+mod rate_limit;
+mod risk;
+mod snapshot;
+mod token_list;
+
+/// Redelivery delay of a `PairCreated` event whose token reads failed on the
+/// RPC side rather than in the token.
+const RETRY_DELAY: Duration = Duration::from_secs(10);
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let app_cfg = init::AppConfig::from_file_or_cli()?;
+    let (app_cfg, app_cmd) = init::AppConfig::from_file_or_cli()?;
     app_cfg.init_log()?;
     info!("starting enrich-pair with config: {app_cfg:#?}");
 
-    let eth_reader = pair_erc20::EthReader::new(&app_cfg.eth_node.http_url).await?;
+    match app_cmd {
+        None => run_enricher(app_cfg).await?,
+        Some(Commands::ScanFactory {
+            from_index,
+            to_index,
+            ..
+        }) => run_scan_factory(app_cfg, from_index, to_index).await?,
+        Some(Commands::Pipeline(cmd)) => run_pipeline_admin(app_cfg, cmd).await?,
+        Some(Commands::Kv(cmd)) => run_kv_admin(app_cfg, cmd).await?,
+    }
+
+    Ok(())
+}
+
+async fn run_enricher(app_cfg: AppConfig) -> Result<()> {
+    let eth_reader =
+        pair_erc20::EthReader::new(&app_cfg.eth_node.http_url, app_cfg.rate_limiter()).await?;
+    let chain_id = eth_reader.chain_id().await?;
+    let token_list = token_list::TokenList::load(app_cfg.token_list.as_ref(), chain_id)?;
 
     let mq_client = mq::MqClient::new(
         &app_cfg.nats.server_url,
@@ -26,48 +58,216 @@ async fn main() -> Result<()> {
     .await?;
 
     let kv = mq_client.kv_store(&app_cfg.nats.kv_bucket).await?;
+    let snapshots = app_cfg
+        .nats
+        .subject_sync
+        .as_deref()
+        .map(|subject| snapshot::SnapshotPublisher::new(&eth_reader, &mq_client, subject));
+    let risk = app_cfg
+        .risk
+        .enabled
+        .then(|| risk::RiskAnalyzer::new(&eth_reader, &app_cfg.risk));
+    let enricher = enricher::Enricher {
+        token_list: &token_list,
+        risk: risk.as_ref(),
+        snapshots: snapshots.as_ref(),
+        kv: &kv,
+        mq_client: &mq_client,
+        subject_output: &app_cfg.nats.subject_output,
+    };
+
     if let Some(addrs) = &app_cfg.uniswap_v2.pair_address {
         for addr in addrs {
             let key = Address::from_str(addr)?;
             let pair = eth_reader.fetch_pair(key).await?;
-            let value = serde_json::to_vec(&pair)?;
-            kv.put(key.to_string(), value.into()).await?;
-            info!("put pair {} to kv store", key);
+            enricher.store(pair).await?;
         }
     }
 
-    let mut sub = mq_client.jetstream_pull_from(true).await?;
-    info!("listening on {}", app_cfg.nats.subject_input);
-
-    while let Some(msg_result) = sub.next().await {
-        let msg = msg_result?;
-        let text = String::from_utf8_lossy(&msg.payload);
-        info!("received raw : {}", text);
-
-        let event: PairCreatedEvent =
-            serde_json::from_slice(&msg.payload).map_err(|e| eyre::eyre!("invalid json: {e}"))?;
-        info!(
-            "fetching pair {} token0={} token1={}",
-            event.pair, event.token0, event.token1
-        );
-        match eth_reader
-            .fetch_pair_token(event.pair, event.token0, event.token1)
-            .await
-        {
-            Ok(pair) => {
-                let value = serde_json::to_vec(&pair)?;
-                kv.put(event.pair.to_string(), value.into()).await?;
-                info!("put pair {} to kv store", event.pair);
-            }
-            Err(e) => {
-                tracing::warn!("skip pair {}: {}", event.pair, e);
-            }
-        };
-
-        msg.ack()
-            .await
-            .map_err(|e| eyre::eyre!("ack message failed: {e}"))?;
+    let concurrency = app_cfg.enricher.concurrency.max(1);
+    let sub = mq_client.jetstream_pull_from(true).await?;
+    info!(
+        "listening on {} with concurrency {concurrency}",
+        app_cfg.nats.subject_input
+    );
+
+    // Each message is enriched and acked independently; a slow RPC call only
+    // holds up its own slot.
+    let mut results = sub
+        .map(|msg_result| {
+            enrich_message(
+                msg_result,
+                &eth_reader,
+                &enricher,
+                app_cfg.eth_node.pin_block,
+            )
+        })
+        .buffer_unordered(concurrency);
+
+    while let Some(result) = results.next().await {
+        result?;
     }
 
     Ok(())
 }
+
+async fn enrich_message(
+    msg_result: Result<async_nats::jetstream::Message>,
+    eth_reader: &pair_erc20::EthReader,
+    enricher: &enricher::Enricher<'_>,
+    pin_block: bool,
+) -> Result<()> {
+    let msg = msg_result?;
+    let text = String::from_utf8_lossy(&msg.payload);
+    info!("received raw : {}", text);
+
+    let event: PairCreatedEvent =
+        serde_json::from_slice(&msg.payload).map_err(|e| eyre!("invalid json: {e}"))?;
+    info!(
+        "fetching pair {} token0={} token1={}",
+        event.pair, event.token0, event.token1
+    );
+    // Events without a block number (block_number = 0) are read at latest.
+    let at_block = (pin_block && event.block_number > 0).then_some(event.block_number);
+    match eth_reader
+        .fetch_pair_token(event.pair, event.token0, event.token1, at_block)
+        .await
+    {
+        Ok(mut pair) => {
+            pair.factory = event.factory;
+            pair.created_block = (event.block_number > 0).then_some(event.block_number);
+            enricher.store(pair).await?;
+        }
+        Err(e) if pair_erc20::is_token_failure(&e) => {
+            warn!("skip pair {}: {}", event.pair, e);
+        }
+        Err(e) => {
+            warn!(
+                "fetching pair {} failed, redelivering in {RETRY_DELAY:?}: {e}",
+                event.pair
+            );
+            msg.ack_with(AckKind::Nak(Some(RETRY_DELAY)))
+                .await
+                .map_err(|e| eyre!("nak message failed: {e}"))?;
+            return Ok(());
+        }
+    };
+
+    msg.ack()
+        .await
+        .map_err(|e| eyre!("ack message failed: {e}"))?;
+    Ok(())
+}
+
+async fn run_scan_factory(
+    app_cfg: AppConfig,
+    from_index: Option<u64>,
+    to_index: Option<u64>,
+) -> Result<()> {
+    let factory_address = app_cfg
+        .uniswap_v2
+        .factory_address
+        .as_deref()
+        .ok_or_else(|| eyre!("uniswap_v2.factory_address is required for scan-factory"))?;
+    let factory = Address::from_str(factory_address)?;
+
+    let eth_reader =
+        pair_erc20::EthReader::new(&app_cfg.eth_node.http_url, app_cfg.rate_limiter()).await?;
+    let chain_id = eth_reader.chain_id().await?;
+    let token_list = token_list::TokenList::load(app_cfg.token_list.as_ref(), chain_id)?;
+
+    let mq_client = mq::MqClient::new(
+        &app_cfg.nats.server_url,
+        &app_cfg.nats.subject_input,
+        &app_cfg.nats.stream_name,
+    )
+    .await?;
+
+    let kv = mq_client.kv_store(&app_cfg.nats.kv_bucket).await?;
+    let cursor = mq_client
+        .kv_store(&app_cfg.factory_scan.cursor_bucket)
+        .await?;
+
+    let snapshots = app_cfg
+        .nats
+        .subject_sync
+        .as_deref()
+        .map(|subject| snapshot::SnapshotPublisher::new(&eth_reader, &mq_client, subject));
+
+    let risk = app_cfg
+        .risk
+        .enabled
+        .then(|| risk::RiskAnalyzer::new(&eth_reader, &app_cfg.risk));
+    let enricher = enricher::Enricher {
+        token_list: &token_list,
+        risk: risk.as_ref(),
+        snapshots: snapshots.as_ref(),
+        kv: &kv,
+        mq_client: &mq_client,
+        subject_output: &app_cfg.nats.subject_output,
+    };
+
+    let scanner =
+        factory::FactoryScanner::new(&eth_reader, &enricher, factory, &app_cfg.factory_scan)?;
+    scanner.run(&cursor, from_index, to_index).await
+}
+
+async fn run_kv_admin(app_cfg: AppConfig, cmd: KvCommand) -> Result<()> {
+    let mq_client = mq::MqClient::new(
+        &app_cfg.nats.server_url,
+        &app_cfg.nats.subject_input,
+        &app_cfg.nats.stream_name,
+    )
+    .await?;
+    let kv = mq_client.kv_store(&app_cfg.nats.kv_bucket).await?;
+
+    match cmd {
+        KvCommand::Remove { pair } => admin::remove(&kv, Address::from_str(&pair)?).await,
+        KvCommand::List => admin::list(&kv).await,
+        KvCommand::Export { output } => admin::export(&kv, output.as_deref()).await,
+        KvCommand::Import { input } => admin::import(&kv, input.as_deref()).await,
+    }
+}
+
+/// `add` and `refresh` run the same pipeline as the event consumer.
+async fn run_pipeline_admin(app_cfg: AppConfig, cmd: PipelineCommand) -> Result<()> {
+    let mq_client = mq::MqClient::new(
+        &app_cfg.nats.server_url,
+        &app_cfg.nats.subject_input,
+        &app_cfg.nats.stream_name,
+    )
+    .await?;
+    let kv = mq_client.kv_store(&app_cfg.nats.kv_bucket).await?;
+
+    let eth_reader =
+        pair_erc20::EthReader::new(&app_cfg.eth_node.http_url, app_cfg.rate_limiter()).await?;
+    let chain_id = eth_reader.chain_id().await?;
+    let token_list = token_list::TokenList::load(app_cfg.token_list.as_ref(), chain_id)?;
+    let snapshots = app_cfg
+        .nats
+        .subject_sync
+        .as_deref()
+        .map(|subject| snapshot::SnapshotPublisher::new(&eth_reader, &mq_client, subject));
+    let risk = app_cfg
+        .risk
+        .enabled
+        .then(|| risk::RiskAnalyzer::new(&eth_reader, &app_cfg.risk));
+    let enricher = enricher::Enricher {
+        token_list: &token_list,
+        risk: risk.as_ref(),
+        snapshots: snapshots.as_ref(),
+        kv: &kv,
+        mq_client: &mq_client,
+        subject_output: &app_cfg.nats.subject_output,
+    };
+
+    match cmd {
+        PipelineCommand::Add { pair } => {
+            admin::add(&eth_reader, &enricher, Address::from_str(&pair)?).await
+        }
+        PipelineCommand::Refresh { pair, .. } => {
+            let pair = pair.as_deref().map(Address::from_str).transpose()?;
+            admin::refresh(&eth_reader, &enricher, &kv, pair).await
+        }
+    }
+}
@@ -1,6 +1,6 @@
 use async_nats::{
     jetstream::{self, consumer::DeliverPolicy, kv::Store},
-    Client, Subscriber,
+    Client,
 };
 use eyre::{Result, WrapErr};
 use futures_util::StreamExt;
@@ -23,8 +23,12 @@ impl MqClient {
         })
     }
 
-    pub async fn subscribe(&self) -> Result<Subscriber> {
-        Ok(self.nats.subscribe(self.subject_input.clone()).await?)
+    pub async fn produce_record(&self, subject: &str, record: String) -> Result<()> {
+        self.nats
+            .publish(subject.to_string(), record.into())
+            .await
+            .map_err(|e| eyre::eyre!("NATS publish failed: {}", e))?;
+        Ok(())
     }
 
     pub async fn jetstream_pull_from(
@@ -1,10 +1,18 @@
-use alloy::primitives::Address;
-use alloy::providers::{DynProvider, Provider, ProviderBuilder};
+use alloy::eips::{BlockId, BlockNumberOrTag};
+use alloy::primitives::{address, aliases::U112, Address, FixedBytes, B256, U256};
+use alloy::providers::{DynProvider, MulticallItem, Provider, ProviderBuilder};
+use alloy::rpc::types::state::{AccountOverride, StateOverride};
+use alloy::rpc::types::TransactionRequest;
 use alloy::sol;
+use alloy::sol_types::SolCall;
 use eyre::Result;
 use serde::Serialize;
+use std::ops::Range;
+use tracing::warn;
 
-use chain_model::{Pair, Token};
+use chain_model::{Pair, SyncEvent, Token};
+
+use crate::rate_limit::RateLimiter;
 
 sol!(
     #[allow(missing_docs)]
@@ -23,65 +31,620 @@ sol!(
     "abi/UniswapV2Pair.json"
 );
 
+sol!(
+    #[allow(missing_docs)]
+    #[sol(rpc)]
+    #[derive(Debug, Serialize)]
+    UniswapV2Factory,
+    "abi/UniswapV2Factory.json"
+);
+
+sol! {
+    #[sol(rpc)]
+    interface TokenProbe {
+        function owner() external view returns (address);
+        function balanceOf(address account) external view returns (uint256);
+        function transfer(address to, uint256 amount) external returns (bool);
+    }
+
+    interface TransferProbe {
+        function probe(address token, address to, uint256 amount)
+            external
+            returns (bool transferred, uint256 received);
+        function roundTrip(address token, address holder, uint256 amount)
+            external
+            returns (bool bought, uint256 received, bool sold, uint256 returned);
+    }
+}
+
+/// Runtime code assembled from `probe/transfer_probe.etk` by `build.rs`.
+const TRANSFER_PROBE_CODE: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/transfer_probe.bin"));
+
+/// Address the pair's own code is moved to while [`TRANSFER_PROBE_CODE`]
+/// replaces it; must match the `push20` in `probe/transfer_probe.etk`.
+const TRANSFER_PROBE_SHADOW: Address = address!("0x00000000000000000000000000000000C0FFEE01");
+
+/// A buy out of a pair followed by a sell of what arrived back into it.
+#[derive(Debug, Clone, Copy)]
+pub struct RoundTrip {
+    /// Amount the holder received, `None` if the buy reverted.
+    pub bought: Option<U256>,
+    /// Amount the pair received back, `None` if the sell reverted or
+    /// nothing was bought.
+    pub sold: Option<U256>,
+}
+
+/// Token addresses and reserves of a pair, read in one multicall pass.
+#[derive(Debug, Clone)]
+pub struct PairInfo {
+    pub address: Address,
+    pub token0: Address,
+    pub token1: Address,
+    pub reserve0: U112,
+    pub reserve1: U112,
+}
+
 pub struct EthReader {
     pub http_provider: DynProvider,
+    rate_limiter: Option<RateLimiter>,
 }
 
 impl EthReader {
-    pub async fn new(http_url: &str) -> Result<Self> {
+    pub async fn new(http_url: &str, rate_limiter: Option<RateLimiter>) -> Result<Self> {
         let url = http_url.parse()?;
         let provider = ProviderBuilder::new().connect_http(url);
         let http_provider = provider.erased();
 
-        Ok(Self { http_provider })
+        Ok(Self {
+            http_provider,
+            rate_limiter,
+        })
+    }
+
+    /// Waits for the rate limiter before each RPC request.
+    async fn throttle(&self) {
+        if let Some(limiter) = &self.rate_limiter {
+            limiter.acquire().await;
+        }
     }
 
-    pub async fn fetch_token(&self, token_address: Address) -> Result<Token> {
+    async fn throttle_n(&self, requests: usize) {
+        for _ in 0..requests {
+            self.throttle().await;
+        }
+    }
+
+    pub async fn chain_id(&self) -> Result<u64> {
+        self.throttle().await;
+        Ok(self.http_provider.get_chain_id().await?)
+    }
+
+    pub async fn block_number(&self) -> Result<u64> {
+        self.throttle().await;
+        Ok(self.http_provider.get_block_number().await?)
+    }
+
+    pub async fn fetch_token(&self, token_address: Address, block: BlockId) -> Result<Token> {
         let contract = ERC20Token::new(token_address, &self.http_provider);
 
-        let decimals = contract.decimals().call().await?;
-        let symbol = contract.symbol().call().await?;
-        let total_supply = contract.totalSupply().call().await?;
+        self.throttle().await;
+        let decimals = contract.decimals().block(block).call().await?;
+        self.throttle().await;
+        let symbol = contract.symbol().block(block).call().await?;
+        self.throttle().await;
+        let total_supply = contract.totalSupply().block(block).call().await?;
 
         Ok(Token {
             address: token_address,
             decimals,
             symbol,
             total_supply,
+            verified: false,
+            risk: None,
         })
     }
 
+    /// Reads token metadata at `at_block` when given, so replays of old
+    /// `PairCreated` events see the same state. Falls back to `latest` only if
+    /// the node has no state for that block (non-archive node); any other
+    /// error is returned.
     pub async fn fetch_pair_token(
         &self,
         pair_address: Address,
         token0: Address,
         token1: Address,
+        at_block: Option<u64>,
     ) -> Result<Pair> {
-        // let contract = UniswapV2Pair::new(pair_address, &self.http_provider);
-        // let token0 = contract.token0().call().await?;
-        // let token1 = contract.token1().call().await?;
+        if let Some(block_number) = at_block {
+            match self
+                .fetch_pair_token_at(pair_address, token0, token1, BlockId::number(block_number))
+                .await
+            {
+                Ok(pair) => return Ok(pair),
+                Err(e) if e.downcast_ref().is_some_and(is_missing_state) => warn!(
+                    "pair {pair_address} metadata at block {block_number} unavailable, using latest: {e}"
+                ),
+                Err(e) => return Err(e),
+            }
+        }
+        self.fetch_pair_token_at(pair_address, token0, token1, BlockId::latest())
+            .await
+    }
 
-        let (token0_res, token1_res) =
-            tokio::join!(self.fetch_token(token0), self.fetch_token(token1));
+    async fn fetch_pair_token_at(
+        &self,
+        pair_address: Address,
+        token0: Address,
+        token1: Address,
+        block: BlockId,
+    ) -> Result<Pair> {
+        let (token0_res, token1_res) = tokio::join!(
+            self.fetch_token(token0, block),
+            self.fetch_token(token1, block)
+        );
 
         Ok(Pair {
             address: pair_address,
             token0: token0_res?,
             token1: token1_res?,
+            factory: None,
+            created_block: None,
         })
     }
+
     pub async fn fetch_pair(&self, pair_address: Address) -> Result<Pair> {
         let contract = UniswapV2Pair::new(pair_address, &self.http_provider);
+        self.throttle().await;
         let token0 = contract.token0().call().await?;
+        self.throttle().await;
         let token1 = contract.token1().call().await?;
 
-        let (token0_res, token1_res) =
-            tokio::join!(self.fetch_token(token0), self.fetch_token(token1));
+        self.fetch_pair_token_at(pair_address, token0, token1, BlockId::latest())
+            .await
+    }
+
+    pub async fn all_pairs_length(&self, factory_address: Address) -> Result<u64> {
+        let contract = UniswapV2Factory::new(factory_address, &self.http_provider);
+        self.throttle().await;
+        let length = contract.allPairsLength().call().await?;
+        Ok(length.saturating_to())
+    }
 
-        Ok(Pair {
-            address: pair_address,
-            token0: token0_res?,
-            token1: token1_res?,
+    /// Reads `allPairs(i)` for every index in `range` with a single multicall.
+    pub async fn all_pairs(
+        &self,
+        factory_address: Address,
+        range: Range<u64>,
+    ) -> Result<Vec<Address>> {
+        let contract = UniswapV2Factory::new(factory_address, &self.http_provider);
+        self.throttle().await;
+        let pairs = self
+            .http_provider
+            .multicall()
+            .dynamic()
+            .extend(range.map(|i| contract.allPairs(U256::from(i))))
+            .aggregate()
+            .await?;
+        Ok(pairs)
+    }
+
+    /// Reads `token0`, `token1` and `getReserves` of each pair at `block_number`.
+    /// Pairs whose calls revert are returned as `None`.
+    pub async fn fetch_pair_infos(
+        &self,
+        pair_addresses: &[Address],
+        block_number: u64,
+    ) -> Result<Vec<Option<PairInfo>>> {
+        let block = BlockId::number(block_number);
+        let contracts: Vec<_> = pair_addresses
+            .iter()
+            .map(|addr| UniswapV2Pair::new(*addr, &self.http_provider))
+            .collect();
+
+        let token0_calls = self
+            .http_provider
+            .multicall()
+            .block(block)
+            .dynamic()
+            .extend_calls(contracts.iter().map(|c| c.token0().into_call(true)));
+        let token1_calls = self
+            .http_provider
+            .multicall()
+            .block(block)
+            .dynamic()
+            .extend_calls(contracts.iter().map(|c| c.token1().into_call(true)));
+        let reserve_calls = self
+            .http_provider
+            .multicall()
+            .block(block)
+            .dynamic()
+            .extend_calls(contracts.iter().map(|c| c.getReserves().into_call(true)));
+
+        self.throttle_n(3).await;
+        let (token0s, token1s, reserves) = tokio::try_join!(
+            token0_calls.aggregate3(),
+            token1_calls.aggregate3(),
+            reserve_calls.aggregate3()
+        )?;
+
+        Ok(pair_addresses
+            .iter()
+            .zip(token0s)
+            .zip(token1s)
+            .zip(reserves)
+            .map(|(((address, token0), token1), reserves)| {
+                let reserves = reserves.ok()?;
+                Some(PairInfo {
+                    address: *address,
+                    token0: token0.ok()?,
+                    token1: token1.ok()?,
+                    reserve0: reserves._reserve0,
+                    reserve1: reserves._reserve1,
+                })
+            })
+            .collect())
+    }
+
+    /// Batched variant of [`Self::fetch_token`]. Tokens with a reverting or
+    /// non-standard `decimals`/`symbol`/`totalSupply` are returned as `None`.
+    pub async fn fetch_tokens(&self, token_addresses: &[Address]) -> Result<Vec<Option<Token>>> {
+        let contracts: Vec<_> = token_addresses
+            .iter()
+            .map(|addr| ERC20Token::new(*addr, &self.http_provider))
+            .collect();
+
+        let decimals_calls = self
+            .http_provider
+            .multicall()
+            .dynamic()
+            .extend_calls(contracts.iter().map(|c| c.decimals().into_call(true)));
+        let symbol_calls = self
+            .http_provider
+            .multicall()
+            .dynamic()
+            .extend_calls(contracts.iter().map(|c| c.symbol().into_call(true)));
+        let supply_calls = self
+            .http_provider
+            .multicall()
+            .dynamic()
+            .extend_calls(contracts.iter().map(|c| c.totalSupply().into_call(true)));
+
+        self.throttle_n(3).await;
+        let (decimals, symbols, supplies) = tokio::try_join!(
+            decimals_calls.aggregate3(),
+            symbol_calls.aggregate3(),
+            supply_calls.aggregate3()
+        )?;
+
+        Ok(token_addresses
+            .iter()
+            .zip(decimals)
+            .zip(symbols)
+            .zip(supplies)
+            .map(|(((address, decimals), symbol), total_supply)| {
+                Some(Token {
+                    address: *address,
+                    decimals: decimals.ok()?,
+                    symbol: symbol.ok()?,
+                    total_supply: total_supply.ok()?,
+                    verified: false,
+                    risk: None,
+                })
+            })
+            .collect())
+    }
+
+    pub async fn block_timestamp(&self, block_number: u64) -> Result<u64> {
+        self.throttle().await;
+        let block = self
+            .http_provider
+            .get_block_by_number(BlockNumberOrTag::Number(block_number))
+            .await?
+            .ok_or_else(|| eyre::eyre!("block {block_number} not found"))?;
+        Ok(block.header.timestamp)
+    }
+
+    /// Reads `getReserves()` at the latest block as a synthetic `Sync` event.
+    pub async fn fetch_reserves_snapshot(&self, pair_address: Address) -> Result<SyncEvent> {
+        let block_number = self.block_number().await?;
+        let contract = UniswapV2Pair::new(pair_address, &self.http_provider);
+        self.throttle().await;
+        let reserves = contract
+            .getReserves()
+            .block(BlockId::number(block_number))
+            .call()
+            .await?;
+        let block_timestamp = self.block_timestamp(block_number).await?;
+
+        Ok(SyncEvent {
+            pair: pair_address,
+            reserve0: reserves._reserve0,
+            reserve1: reserves._reserve1,
+            transaction_hash: FixedBytes::ZERO,
+            log_index: Some(0),
+            block_number,
+            block_timestamp,
+            snapshot: true,
+        })
+    }
+
+    pub async fn has_code(&self, address: Address) -> Result<bool> {
+        self.throttle().await;
+        let code = self.http_provider.get_code_at(address).await?;
+        Ok(!code.is_empty())
+    }
+
+    pub async fn storage_at(&self, address: Address, slot: B256) -> Result<U256> {
+        self.throttle().await;
+        let value = self
+            .http_provider
+            .get_storage_at(address, slot.into())
+            .await?;
+        Ok(value)
+    }
+
+    /// `owner()` of an Ownable token, `None` if the token has no such
+    /// function. Failures of the request itself are returned.
+    pub async fn token_owner(&self, token_address: Address) -> Result<Option<Address>> {
+        let contract = TokenProbe::new(token_address, &self.http_provider);
+        self.throttle().await;
+        match contract.owner().call().await {
+            Ok(owner) => Ok(Some(owner)),
+            Err(e) if is_call_failure(&e) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub async fn token_balance(&self, token_address: Address, holder: Address) -> Result<U256> {
+        let contract = TokenProbe::new(token_address, &self.http_provider);
+        self.throttle().await;
+        Ok(contract.balanceOf(holder).call().await?)
+    }
+
+    /// Runs `roundTrip` of [`TRANSFER_PROBE_CODE`] in an `eth_call` with the
+    /// probe installed at `pair` and `holder`: `pair` transfers `amount` to
+    /// `holder`, which transfers what it received back.
+    pub async fn simulate_round_trip(
+        &self,
+        token_address: Address,
+        pair: Address,
+        holder: Address,
+        amount: U256,
+    ) -> Result<RoundTrip> {
+        self.throttle().await;
+        let pair_code = self.http_provider.get_code_at(pair).await?;
+
+        let mut state_overrides = StateOverride::default();
+        for (address, code) in [
+            (pair, TRANSFER_PROBE_CODE.into()),
+            (holder, TRANSFER_PROBE_CODE.into()),
+            (TRANSFER_PROBE_SHADOW, pair_code),
+        ] {
+            state_overrides.insert(
+                address,
+                AccountOverride {
+                    code: Some(code),
+                    ..Default::default()
+                },
+            );
+        }
+
+        let input = TransferProbe::roundTripCall {
+            token: token_address,
+            holder,
+            amount,
+        }
+        .abi_encode();
+        let request = TransactionRequest::default().to(pair).input(input.into());
+
+        self.throttle().await;
+        let output = self
+            .http_provider
+            .call(request)
+            .overrides(state_overrides)
+            .await?;
+        let result = TransferProbe::roundTripCall::abi_decode_returns(&output)?;
+        Ok(RoundTrip {
+            bought: result.bought.then_some(result.received),
+            sold: result.sold.then_some(result.returned),
         })
     }
 }
+
+/// Whether the call itself failed (a revert, or no or undecodable return
+/// data) rather than the RPC request carrying it.
+fn is_call_failure(e: &alloy::contract::Error) -> bool {
+    match e {
+        alloy::contract::Error::ZeroData(..) | alloy::contract::Error::AbiError(_) => true,
+        alloy::contract::Error::TransportError(e) => e
+            .as_error_resp()
+            .is_some_and(|resp| resp.message.contains("revert")),
+        _ => false,
+    }
+}
+
+/// Whether a token read failed in the token itself (see [`is_call_failure`]),
+/// so retrying it cannot succeed.
+pub fn is_token_failure(e: &eyre::Report) -> bool {
+    e.downcast_ref::<alloy::contract::Error>()
+        .is_some_and(is_call_failure)
+}
+
+/// Error messages nodes return for a block whose state they no longer hold.
+const MISSING_STATE_ERRORS: &[&str] = &[
+    "missing trie node",
+    "historical state",
+    "header not found",
+    "is pruned",
+];
+
+/// Whether the node could not serve the requested block's state, rather than
+/// the call failing at that block.
+fn is_missing_state(e: &alloy::contract::Error) -> bool {
+    let alloy::contract::Error::TransportError(e) = e else {
+        return false;
+    };
+    e.as_error_resp().is_some_and(|resp| {
+        let message = resp.message.to_lowercase();
+        MISSING_STATE_ERRORS
+            .iter()
+            .any(|pattern| message.contains(pattern))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use revm::bytecode::Bytecode;
+    use revm::context::result::{ExecutionResult, Output};
+    use revm::context::{Context, TxEnv};
+    use revm::database::{CacheDB, EmptyDB};
+    use revm::primitives::TxKind;
+    use revm::state::AccountInfo;
+    use revm::{ExecuteEvm, MainBuilder, MainContext};
+
+    /// Assembled from `probe/test_token.etk`.
+    const TEST_TOKEN_CODE: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/test_token.bin"));
+
+    const PAIR: Address = address!("0x00000000000000000000000000000000000000aa");
+    const TOKEN: Address = address!("0x00000000000000000000000000000000000000bb");
+    const HOLDER: Address = address!("0x00000000000000000000000000000000C0FFEE00");
+
+    /// Stand-in for the pair's own code: answers every call with 123.
+    const PAIR_CODE: &[u8] = &[0x60, 0x7b, 0x60, 0x00, 0x52, 0x60, 0x20, 0x60, 0x00, 0xf3];
+
+    const AMOUNT: u64 = 1_000_000_000_000_000_000;
+
+    struct TestToken {
+        fee_bps: u64,
+        blocked_recipient: Address,
+        callback: Address,
+    }
+
+    impl Default for TestToken {
+        fn default() -> Self {
+            Self {
+                fee_bps: 0,
+                blocked_recipient: Address::ZERO,
+                callback: Address::ZERO,
+            }
+        }
+    }
+
+    fn round_trip(token: TestToken) -> TransferProbe::roundTripReturn {
+        let slot = |address: Address| U256::from_be_slice(address.as_slice());
+        let code =
+            |code: &[u8]| AccountInfo::from_bytecode(Bytecode::new_raw(code.to_vec().into()));
+
+        let mut db = CacheDB::new(EmptyDB::default());
+        db.insert_account_info(PAIR, code(TRANSFER_PROBE_CODE));
+        db.insert_account_info(HOLDER, code(TRANSFER_PROBE_CODE));
+        db.insert_account_info(TRANSFER_PROBE_SHADOW, code(PAIR_CODE));
+        db.insert_account_info(TOKEN, code(TEST_TOKEN_CODE));
+        for (key, value) in [
+            (slot(PAIR), U256::from(AMOUNT) * U256::from(100)),
+            (U256::from(1), U256::from(token.fee_bps)),
+            (U256::from(2), slot(token.blocked_recipient)),
+            (U256::from(3), slot(token.callback)),
+        ] {
+            db.insert_account_storage(TOKEN, key, value).unwrap();
+        }
+
+        let input = TransferProbe::roundTripCall {
+            token: TOKEN,
+            holder: HOLDER,
+            amount: U256::from(AMOUNT),
+        }
+        .abi_encode();
+        let tx = TxEnv::builder()
+            .kind(TxKind::Call(PAIR))
+            .data(input.into())
+            .gas_limit(1_000_000)
+            .build_fill();
+        let mut evm = Context::mainnet().with_db(db).build_mainnet();
+        match evm.transact(tx).unwrap().result {
+            ExecutionResult::Success {
+                output: Output::Call(output),
+                ..
+            } => TransferProbe::roundTripCall::abi_decode_returns(&output).unwrap(),
+            other => panic!("round trip failed: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn plain_token_round_trips_in_full() {
+        let result = round_trip(TestToken::default());
+        assert!(result.bought && result.sold);
+        assert_eq!(result.received, U256::from(AMOUNT));
+        assert_eq!(result.returned, U256::from(AMOUNT));
+    }
+
+    #[test]
+    fn fee_on_transfer_is_measured_both_ways() {
+        let result = round_trip(TestToken {
+            fee_bps: 500,
+            ..Default::default()
+        });
+        assert!(result.bought && result.sold);
+        assert_eq!(result.received, U256::from(AMOUNT / 100 * 95));
+        assert_eq!(result.returned, U256::from(AMOUNT / 10_000 * 9025));
+    }
+
+    #[test]
+    fn blocked_buy_is_not_sold() {
+        let result = round_trip(TestToken {
+            blocked_recipient: HOLDER,
+            ..Default::default()
+        });
+        assert!(!result.bought && !result.sold);
+        assert_eq!(result.received, U256::ZERO);
+    }
+
+    #[test]
+    fn blocked_sell_is_reported() {
+        let result = round_trip(TestToken {
+            blocked_recipient: PAIR,
+            ..Default::default()
+        });
+        assert!(result.bought && !result.sold);
+        assert_eq!(result.received, U256::from(AMOUNT));
+        assert_eq!(result.returned, U256::ZERO);
+    }
+
+    #[test]
+    fn calls_into_the_pair_reach_its_code() {
+        let result = round_trip(TestToken {
+            callback: PAIR,
+            ..Default::default()
+        });
+        assert!(result.bought && result.sold);
+    }
+
+    fn rpc_error(code: i64, message: &str) -> alloy::contract::Error {
+        let payload = serde_json::json!({ "code": code, "message": message });
+        alloy::transports::RpcError::ErrorResp(serde_json::from_value(payload).unwrap()).into()
+    }
+
+    #[test]
+    fn only_reverts_count_as_call_failures() {
+        assert!(is_call_failure(&rpc_error(3, "execution reverted")));
+        assert!(!is_call_failure(&rpc_error(-32005, "rate limit exceeded")));
+        assert!(!is_call_failure(
+            &alloy::transports::TransportErrorKind::backend_gone().into()
+        ));
+    }
+
+    #[test]
+    fn only_pruned_state_falls_back_to_latest() {
+        assert!(is_missing_state(&rpc_error(
+            -32000,
+            "missing trie node 1f2e (path ) state 0x1f2e is not available"
+        )));
+        assert!(is_missing_state(&rpc_error(
+            -32000,
+            "state at block #1000 is pruned"
+        )));
+        assert!(!is_missing_state(&rpc_error(3, "execution reverted")));
+        assert!(!is_missing_state(&rpc_error(-32005, "rate limit exceeded")));
+    }
+}
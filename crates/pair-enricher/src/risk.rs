@@ -0,0 +1,184 @@
+use alloy::primitives::{address, b256, Address, B256, U256};
+use eyre::Result;
+use tracing::{info, warn};
+
+use chain_model::{Pair, RiskFlag, Token, TokenRisk};
+
+use crate::init::RiskConfig;
+use crate::pair_erc20::EthReader;
+
+/// `bytes32(uint256(keccak256("eip1967.proxy.implementation")) - 1)`
+const EIP1967_IMPLEMENTATION_SLOT: B256 =
+    b256!("360894a13ba1a3210667c828492db98dca3e2076cc3735a920a3ca505d382bbc");
+/// `bytes32(uint256(keccak256("eip1967.proxy.beacon")) - 1)`
+const EIP1967_BEACON_SLOT: B256 =
+    b256!("a3f0ad74e5423aebfd80d3ef4346578335a9a72aeaee59ff6cb3582b35133d50");
+
+/// Buyer and seller in the simulated round trip; any address without special
+/// handling in the token works since amounts are balance differences.
+const PROBE_HOLDER: Address = address!("0x00000000000000000000000000000000C0FFEE00");
+
+/// Share of the pair's balance bought in the simulated round trip.
+const PROBE_TRANSFER_DIVISOR: u64 = 100;
+
+/// Cheap on-chain checks that catch the common honeypot and fee-on-transfer
+/// patterns of newly created pairs.
+pub struct RiskAnalyzer<'a> {
+    eth_reader: &'a EthReader,
+    max_owner_supply_bps: u32,
+    max_transfer_fee_bps: u32,
+}
+
+impl<'a> RiskAnalyzer<'a> {
+    pub fn new(eth_reader: &'a EthReader, cfg: &RiskConfig) -> Self {
+        Self {
+            eth_reader,
+            max_owner_supply_bps: cfg.max_owner_supply_bps,
+            max_transfer_fee_bps: cfg.max_transfer_fee_bps,
+        }
+    }
+
+    /// Fills `risk` of both tokens. Verified tokens are trusted and skipped.
+    pub async fn analyze_pair(&self, pair: &mut Pair) {
+        let (risk0, risk1) = tokio::join!(
+            self.analyze_unverified(&pair.token0, pair.address),
+            self.analyze_unverified(&pair.token1, pair.address)
+        );
+        pair.token0.risk = risk0;
+        pair.token1.risk = risk1;
+    }
+
+    async fn analyze_unverified(&self, token: &Token, pair_address: Address) -> Option<TokenRisk> {
+        if token.verified {
+            return None;
+        }
+        let risk = self.analyze_token(token, pair_address).await;
+        if !risk.flags.is_empty() {
+            info!(
+                "token {} ({}) risk flags: {:?}",
+                token.address, token.symbol, risk.flags
+            );
+        }
+        Some(risk)
+    }
+
+    /// Runs every check; one that fails is logged and marks the result
+    /// `incomplete` instead of discarding what the others found.
+    pub async fn analyze_token(&self, token: &Token, pair_address: Address) -> TokenRisk {
+        let mut risk = TokenRisk::default();
+        match self.eth_reader.has_code(token.address).await {
+            Ok(true) => risk.has_code = true,
+            Ok(false) => {
+                risk.flags.push(RiskFlag::NoCode);
+                return risk;
+            }
+            Err(e) => {
+                incomplete(&mut risk, token, "code", e);
+                return risk;
+            }
+        }
+
+        if let Err(e) = self.check_proxy(token, &mut risk).await {
+            incomplete(&mut risk, token, "proxy", e);
+        }
+        if let Err(e) = self.check_owner(token, &mut risk).await {
+            incomplete(&mut risk, token, "owner", e);
+        }
+        if let Err(e) = self.check_transfer(token, pair_address, &mut risk).await {
+            incomplete(&mut risk, token, "transfer", e);
+        }
+        risk
+    }
+
+    async fn check_proxy(&self, token: &Token, risk: &mut TokenRisk) -> Result<()> {
+        let implementation = self
+            .eth_reader
+            .storage_at(token.address, EIP1967_IMPLEMENTATION_SLOT)
+            .await?;
+        let beacon = self
+            .eth_reader
+            .storage_at(token.address, EIP1967_BEACON_SLOT)
+            .await?;
+        risk.is_proxy = !implementation.is_zero() || !beacon.is_zero();
+        if risk.is_proxy {
+            risk.flags.push(RiskFlag::Proxy);
+        }
+        Ok(())
+    }
+
+    async fn check_owner(&self, token: &Token, risk: &mut TokenRisk) -> Result<()> {
+        risk.owner = self.eth_reader.token_owner(token.address).await?;
+        if let Some(owner) = risk.owner.filter(|owner| !owner.is_zero()) {
+            risk.flags.push(RiskFlag::OwnerNotRenounced);
+
+            let balance = self.eth_reader.token_balance(token.address, owner).await?;
+            let share = to_bps(balance, token.total_supply);
+            risk.owner_supply_bps = Some(share);
+            if share > self.max_owner_supply_bps {
+                risk.flags.push(RiskFlag::ConcentratedSupply);
+            }
+        }
+        Ok(())
+    }
+
+    async fn check_transfer(
+        &self,
+        token: &Token,
+        pair_address: Address,
+        risk: &mut TokenRisk,
+    ) -> Result<()> {
+        // Once liquidity is added the pair holds a balance, and a transfer out
+        // of it is exactly what a buy does; honeypots usually block the sell,
+        // the transfer back in.
+        let pair_balance = self
+            .eth_reader
+            .token_balance(token.address, pair_address)
+            .await?;
+        let amount = pair_balance / U256::from(PROBE_TRANSFER_DIVISOR);
+        if amount.is_zero() {
+            return Ok(());
+        }
+        let round_trip = self
+            .eth_reader
+            .simulate_round_trip(token.address, pair_address, PROBE_HOLDER, amount)
+            .await?;
+
+        let Some(received) = round_trip.bought else {
+            risk.flags.push(RiskFlag::TransferReverts);
+            return Ok(());
+        };
+        let fee = to_bps(amount.saturating_sub(received), amount);
+        risk.transfer_fee_bps = Some(fee);
+        if fee > self.max_transfer_fee_bps {
+            risk.flags.push(RiskFlag::TransferFee);
+        }
+        if received.is_zero() {
+            return Ok(());
+        }
+
+        match round_trip.sold {
+            Some(returned) => {
+                let fee = to_bps(received.saturating_sub(returned), received);
+                risk.sell_fee_bps = Some(fee);
+                if fee > self.max_transfer_fee_bps {
+                    risk.flags.push(RiskFlag::SellFee);
+                }
+            }
+            None => risk.flags.push(RiskFlag::SellReverts),
+        }
+        Ok(())
+    }
+}
+
+fn incomplete(risk: &mut TokenRisk, token: &Token, check: &str, e: eyre::Report) {
+    warn!("{check} check of token {} failed: {e}", token.address);
+    risk.incomplete = true;
+}
+
+fn to_bps(part: U256, total: U256) -> u32 {
+    if total.is_zero() {
+        return 0;
+    }
+    let bps = part.saturating_mul(U256::from(10_000u64)) / total;
+    bps.saturating_to()
+}
@@ -0,0 +1,44 @@
+use alloy::primitives::Address;
+use eyre::Result;
+use tracing::info;
+
+use chain_model::SyncEvent;
+
+use crate::mq::MqClient;
+use crate::pair_erc20::EthReader;
+
+/// Publishes `getReserves()` snapshots of newly stored pairs to the sync
+/// subject, so every pair gets a first `PriceTick` without waiting for a `Sync`.
+pub struct SnapshotPublisher<'a> {
+    eth_reader: &'a EthReader,
+    mq_client: &'a MqClient,
+    subject: String,
+}
+
+impl<'a> SnapshotPublisher<'a> {
+    pub fn new(eth_reader: &'a EthReader, mq_client: &'a MqClient, subject: &str) -> Self {
+        Self {
+            eth_reader,
+            mq_client,
+            subject: subject.to_string(),
+        }
+    }
+
+    pub async fn publish(&self, pair_address: Address) -> Result<()> {
+        let event = self
+            .eth_reader
+            .fetch_reserves_snapshot(pair_address)
+            .await?;
+        self.publish_event(&event).await
+    }
+
+    pub async fn publish_event(&self, event: &SyncEvent) -> Result<()> {
+        let msg = serde_json::to_string(event)?;
+        self.mq_client.produce_record(&self.subject, msg).await?;
+        info!(
+            "sent reserve snapshot of {} at block {}",
+            event.pair, event.block_number
+        );
+        Ok(())
+    }
+}
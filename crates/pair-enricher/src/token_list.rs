@@ -0,0 +1,96 @@
+use alloy::primitives::Address;
+use eyre::{Result, WrapErr};
+use serde::Deserialize;
+use std::collections::HashMap;
+use tracing::{info, warn};
+
+use chain_model::{Pair, Token};
+
+use crate::init::TokenListConfig;
+
+/// A Uniswap-format token list, see <https://tokenlists.org>.
+#[derive(Debug, Deserialize)]
+struct TokenListFile {
+    name: String,
+    tokens: Vec<TokenListEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TokenListEntry {
+    chain_id: u64,
+    address: Address,
+    symbol: String,
+    decimals: u8,
+}
+
+/// Curated token metadata that takes precedence over on-chain `symbol()` and
+/// `decimals()`, which any contract can spoof.
+#[derive(Debug, Default)]
+pub struct TokenList {
+    tokens: HashMap<Address, TokenListEntry>,
+    require_listed: bool,
+}
+
+impl TokenList {
+    /// Loads every list in `cfg`, keeping only entries for `chain_id`. Later
+    /// files override earlier ones for the same address.
+    pub fn load(cfg: Option<&TokenListConfig>, chain_id: u64) -> Result<Self> {
+        let Some(cfg) = cfg else {
+            return Ok(Self::default());
+        };
+
+        let mut tokens = HashMap::new();
+        for path in &cfg.paths {
+            let raw = std::fs::read(path).wrap_err_with(|| format!("read token list {path}"))?;
+            let list: TokenListFile = serde_json::from_slice(&raw)
+                .wrap_err_with(|| format!("parse token list {path}"))?;
+            let before = tokens.len();
+            for entry in list.tokens.into_iter().filter(|t| t.chain_id == chain_id) {
+                tokens.insert(entry.address, entry);
+            }
+            info!(
+                "loaded token list '{}' from {path}: {} tokens for chain {chain_id}",
+                list.name,
+                tokens.len() - before
+            );
+        }
+
+        Ok(Self {
+            tokens,
+            require_listed: cfg.require_listed,
+        })
+    }
+
+    /// Marks listed tokens as verified and replaces their symbol and decimals
+    /// with the curated values. Returns `None` if the pair must be rejected.
+    pub fn curate(&self, mut pair: Pair) -> Option<Pair> {
+        self.curate_token(&mut pair.token0);
+        self.curate_token(&mut pair.token1);
+
+        if self.require_listed && !(pair.token0.verified && pair.token1.verified) {
+            warn!(
+                "reject pair {}: {}/{} not on token list",
+                pair.address, pair.token0.symbol, pair.token1.symbol
+            );
+            return None;
+        }
+        Some(pair)
+    }
+
+    fn curate_token(&self, token: &mut Token) {
+        let Some(entry) = self.tokens.get(&token.address) else {
+            token.verified = false;
+            return;
+        };
+        if entry.symbol != token.symbol || entry.decimals != token.decimals {
+            info!(
+                "override token {}: {}/{} -> {}/{}",
+                token.address, token.symbol, token.decimals, entry.symbol, entry.decimals
+            );
+        }
+        token.symbol = entry.symbol.clone();
+        token.decimals = entry.decimals;
+        token.verified = true;
+    }
+}
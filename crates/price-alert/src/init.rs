@@ -0,0 +1,120 @@
+use clap::Parser;
+use config::{Config, File};
+use eyre::Result;
+use serde::Deserialize;
+use std::path::Path;
+use tracing::debug;
+use tracing_subscriber::{fmt, prelude::*, EnvFilter};
+
+use chain_model::AlertKind;
+
+#[derive(Debug, Deserialize)]
+pub struct AppConfig {
+    pub nats: NatsConfig,
+    pub alerts: AlertsConfig,
+    #[serde(default)]
+    pub rules: Vec<RuleConfig>,
+    pub log: Option<LogConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NatsConfig {
+    pub server_url: String,
+    pub subject_input: String,
+    pub stream_name: String,
+    /// Alerts go to `<subject_output>.<kind>`.
+    pub subject_output: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AlertsConfig {
+    /// Blocks during which a rule stays silent for a pair after firing.
+    pub cooldown_blocks: u64,
+}
+
+/// One `[[rules]]` entry; which fields are required depends on `kind`.
+#[derive(Debug, Deserialize)]
+pub struct RuleConfig {
+    pub name: String,
+    pub kind: AlertKind,
+    /// `price_move`, `liquidity_drop`: change in percent.
+    pub percent: Option<f64>,
+    /// `price_move`, `liquidity_drop`: blocks the change happens within.
+    pub blocks: Option<u64>,
+    /// `liquidity_below`, `new_pair_liquidity`: threshold in USD.
+    pub min_liquidity_usd: Option<f64>,
+    /// Overrides `alerts.cooldown_blocks`.
+    pub cooldown_blocks: Option<u64>,
+    /// Only evaluate these pairs; all pairs when absent.
+    pub pairs: Option<Vec<String>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LogConfig {
+    pub level: String,
+}
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Cli {
+    #[arg(long)]
+    server_url: Option<String>,
+    #[arg(long)]
+    subject_input: Option<String>,
+    #[arg(long)]
+    stream_name: Option<String>,
+    #[arg(long)]
+    subject_output: Option<String>,
+    /// TOML file with `[[rules]]`, replacing those of the config file.
+    #[arg(long)]
+    rules: Option<String>,
+    #[arg(long)]
+    cooldown_blocks: Option<u64>,
+}
+
+impl AppConfig {
+    pub fn from_file_or_cli() -> Result<Self> {
+        let cli = Cli::parse();
+        let config_path1 = Path::new("config/price-alert.toml");
+        let config_path2 = Path::new("price-alert.toml");
+
+        let mut builder = Config::builder()
+            .set_default("alerts.cooldown_blocks", 50)?
+            .add_source(File::from(config_path1).required(false))
+            .add_source(File::from(config_path2).required(false));
+        if let Some(rules) = &cli.rules {
+            builder = builder.add_source(File::from(Path::new(rules)));
+        }
+
+        let cfg: AppConfig = builder
+            .set_override_option("nats.server_url", cli.server_url)?
+            .set_override_option("nats.subject_input", cli.subject_input)?
+            .set_override_option("nats.stream_name", cli.stream_name)?
+            .set_override_option("nats.subject_output", cli.subject_output)?
+            .set_override_option("alerts.cooldown_blocks", cli.cooldown_blocks)?
+            .build()
+            .map_err(eyre::Report::from)?
+            .try_deserialize()?;
+        Ok(cfg)
+    }
+
+    pub fn init_log(&self) -> Result<()> {
+        let level_str = self
+            .log
+            .as_ref()
+            .map(|l| l.level.as_str())
+            .unwrap_or("info");
+
+        let filter = EnvFilter::try_from_default_env()
+            .or_else(|_| EnvFilter::try_new(level_str))
+            .map_err(eyre::Report::from)?;
+
+        tracing_subscriber::registry()
+            .with(fmt::layer())
+            .with(filter)
+            .init();
+
+        debug!("log level configured to: '{}'", level_str);
+        Ok(())
+    }
+}
@@ -0,0 +1,48 @@
+use eyre::{eyre, Result};
+use futures_util::StreamExt;
+use tracing::{info, warn};
+
+use chain_model::PriceTick;
+
+mod init;
+mod mq;
+mod rules;
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let app_cfg = init::AppConfig::from_file_or_cli()?;
+    app_cfg.init_log()?;
+    info!("starting price-alert with config: {app_cfg:#?}");
+
+    let mq_client = mq::MqClient::new(
+        &app_cfg.nats.server_url,
+        &app_cfg.nats.subject_input,
+        &app_cfg.nats.stream_name,
+    )
+    .await?;
+
+    let mut engine = rules::RuleEngine::new(&app_cfg.alerts, &app_cfg.rules)?;
+    info!("loaded {} alert rules", engine.len());
+
+    let mut sub = mq_client.jetstream_pull_from(true).await?;
+    while let Some(msg_result) = sub.next().await {
+        let msg = msg_result?;
+        match serde_json::from_slice::<PriceTick>(&msg.payload) {
+            Ok(tick) => {
+                for alert in engine.evaluate(&tick) {
+                    info!("alert '{}': {}", alert.rule, alert.message);
+                    let subject =
+                        format!("{}.{}", app_cfg.nats.subject_output, alert.kind.as_str());
+                    let payload = serde_json::to_string(&alert)?;
+                    mq_client.produce_record(subject, payload).await?;
+                }
+            }
+            Err(e) => warn!("invalid payload: {e}"),
+        }
+        msg.ack()
+            .await
+            .map_err(|e| eyre!("ack message failed: {e}"))?;
+    }
+
+    Ok(())
+}
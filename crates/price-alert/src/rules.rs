@@ -0,0 +1,252 @@
+use alloy::primitives::U256;
+use eyre::{eyre, Result};
+use std::collections::{HashMap, HashSet, VecDeque};
+use tracing::debug;
+
+use chain_model::{Alert, AlertKind, PriceTick};
+
+use crate::init::{AlertsConfig, RuleConfig};
+
+enum Condition {
+    PriceMove { percent: f64, blocks: u64 },
+    LiquidityDrop { percent: f64, blocks: u64 },
+    LiquidityBelow { min_liquidity_usd: f64 },
+    NewPairLiquidity { min_liquidity_usd: f64 },
+}
+
+struct Rule {
+    name: String,
+    kind: AlertKind,
+    condition: Condition,
+    cooldown_blocks: u64,
+    pairs: Option<HashSet<String>>,
+}
+
+impl Rule {
+    fn from_config(cfg: &RuleConfig, default_cooldown: u64) -> Result<Self> {
+        let missing = |field: &str| eyre!("rule '{}' needs `{field}`", cfg.name);
+        let condition = match cfg.kind {
+            AlertKind::PriceMove => Condition::PriceMove {
+                percent: cfg.percent.ok_or_else(|| missing("percent"))?,
+                blocks: cfg.blocks.ok_or_else(|| missing("blocks"))?,
+            },
+            AlertKind::LiquidityDrop => Condition::LiquidityDrop {
+                percent: cfg.percent.ok_or_else(|| missing("percent"))?,
+                blocks: cfg.blocks.ok_or_else(|| missing("blocks"))?,
+            },
+            AlertKind::LiquidityBelow => Condition::LiquidityBelow {
+                min_liquidity_usd: cfg
+                    .min_liquidity_usd
+                    .ok_or_else(|| missing("min_liquidity_usd"))?,
+            },
+            AlertKind::NewPairLiquidity => Condition::NewPairLiquidity {
+                min_liquidity_usd: cfg
+                    .min_liquidity_usd
+                    .ok_or_else(|| missing("min_liquidity_usd"))?,
+            },
+        };
+        Ok(Self {
+            name: cfg.name.clone(),
+            kind: cfg.kind,
+            condition,
+            cooldown_blocks: cfg.cooldown_blocks.unwrap_or(default_cooldown),
+            pairs: cfg
+                .pairs
+                .as_ref()
+                .map(|pairs| pairs.iter().map(|p| p.to_lowercase()).collect()),
+        })
+    }
+
+    fn history_blocks(&self) -> u64 {
+        match self.condition {
+            Condition::PriceMove { blocks, .. } | Condition::LiquidityDrop { blocks, .. } => blocks,
+            _ => 0,
+        }
+    }
+}
+
+struct Observation {
+    block_number: u64,
+    price: f64,
+    /// `sqrt(reserve0 * reserve1)`, which only moves with liquidity, not price.
+    depth: f64,
+}
+
+#[derive(Default)]
+struct PairState {
+    history: VecDeque<Observation>,
+    /// USD liquidity of the first tick seen, `None` when it was not priced.
+    first_liquidity_usd: Option<f64>,
+    liquidity_usd: Option<f64>,
+}
+
+/// Evaluates the configured rules against each tick with per-pair state.
+///
+/// A rule that fired for a pair stays silent for its cooldown, at least the
+/// rest of the block, so redelivered ticks do not repeat an alert.
+/// `new_pair_liquidity` fires at most once per pair.
+pub struct RuleEngine {
+    rules: Vec<Rule>,
+    history_blocks: u64,
+    pairs: HashMap<String, PairState>,
+    last_fired: HashMap<(usize, String), u64>,
+}
+
+impl RuleEngine {
+    pub fn new(cfg: &AlertsConfig, rules: &[RuleConfig]) -> Result<Self> {
+        let rules = rules
+            .iter()
+            .map(|rule| Rule::from_config(rule, cfg.cooldown_blocks))
+            .collect::<Result<Vec<_>>>()?;
+        let history_blocks = rules.iter().map(Rule::history_blocks).max().unwrap_or(0);
+        Ok(Self {
+            rules,
+            history_blocks,
+            pairs: HashMap::new(),
+            last_fired: HashMap::new(),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    pub fn evaluate(&mut self, tick: &PriceTick) -> Vec<Alert> {
+        let pair_key = tick.pair_address.to_lowercase();
+        let state = self
+            .pairs
+            .entry(pair_key.clone())
+            .or_insert_with(|| PairState {
+                first_liquidity_usd: tick.liquidity_usd,
+                liquidity_usd: tick.liquidity_usd,
+                ..Default::default()
+            });
+
+        let reserve0 = f64::from(U256::from(tick.token0_reserve));
+        let reserve1 = f64::from(U256::from(tick.token1_reserve));
+        let current = Observation {
+            block_number: tick.block_number,
+            price: tick.token0_token1,
+            depth: (reserve0 * reserve1).sqrt(),
+        };
+
+        let mut alerts = Vec::new();
+        for (index, rule) in self.rules.iter().enumerate() {
+            if rule
+                .pairs
+                .as_ref()
+                .is_some_and(|pairs| !pairs.contains(&pair_key))
+            {
+                continue;
+            }
+            let Some((value, threshold, message)) = check(rule, state, &current, tick) else {
+                continue;
+            };
+
+            let fired_key = (index, pair_key.clone());
+            let once = rule.kind == AlertKind::NewPairLiquidity;
+            if let Some(&last) = self.last_fired.get(&fired_key) {
+                if once || tick.block_number < last + rule.cooldown_blocks.max(1) {
+                    debug!("rule '{}' cooling down for {}", rule.name, pair_key);
+                    continue;
+                }
+            }
+            self.last_fired.insert(fired_key, tick.block_number);
+
+            alerts.push(Alert {
+                rule: rule.name.clone(),
+                kind: rule.kind,
+                pair_address: tick.pair_address.clone(),
+                token0_symbol: tick.token0_symbol.clone(),
+                token1_symbol: tick.token1_symbol.clone(),
+                value,
+                threshold,
+                message,
+                transaction_hash: tick.transaction_hash.clone(),
+                block_number: tick.block_number,
+                block_timestamp: tick.block_timestamp,
+            });
+        }
+
+        state.liquidity_usd = tick.liquidity_usd;
+        state.history.push_back(current);
+        let oldest = tick.block_number.saturating_sub(self.history_blocks);
+        while state
+            .history
+            .front()
+            .is_some_and(|obs| obs.block_number < oldest)
+        {
+            state.history.pop_front();
+        }
+        alerts
+    }
+}
+
+/// Returns the observed value, the threshold and a message when `rule` holds.
+fn check(
+    rule: &Rule,
+    state: &PairState,
+    current: &Observation,
+    tick: &PriceTick,
+) -> Option<(f64, f64, String)> {
+    let pair = format!("{}/{}", tick.token0_symbol, tick.token1_symbol);
+    let window = |blocks: u64| {
+        let since = current.block_number.saturating_sub(blocks);
+        state
+            .history
+            .iter()
+            .filter(move |obs| obs.block_number >= since)
+    };
+
+    match rule.condition {
+        Condition::PriceMove { percent, blocks } => {
+            let reference = window(blocks).next()?;
+            if reference.price <= 0.0 {
+                return None;
+            }
+            let change = (current.price - reference.price) / reference.price * 100.0;
+            (change.abs() >= percent).then(|| {
+                let message = format!(
+                    "{pair} moved {change:+.2}% since block {}",
+                    reference.block_number
+                );
+                (change, percent, message)
+            })
+        }
+        Condition::LiquidityDrop { percent, blocks } => {
+            let peak = window(blocks).map(|obs| obs.depth).fold(0.0, f64::max);
+            if peak <= 0.0 {
+                return None;
+            }
+            let drop = (peak - current.depth) / peak * 100.0;
+            (drop >= percent).then(|| {
+                let message = format!("{pair} liquidity dropped {drop:.2}% within {blocks} blocks");
+                (drop, percent, message)
+            })
+        }
+        Condition::LiquidityBelow { min_liquidity_usd } => {
+            let liquidity = tick.liquidity_usd?;
+            let previous = state.liquidity_usd?;
+            (previous >= min_liquidity_usd && liquidity < min_liquidity_usd).then(|| {
+                let message = format!(
+                    "{pair} liquidity fell to ${liquidity:.0}, below ${min_liquidity_usd:.0}"
+                );
+                (liquidity, min_liquidity_usd, message)
+            })
+        }
+        Condition::NewPairLiquidity { min_liquidity_usd } => {
+            let liquidity = tick.liquidity_usd?;
+            let below = |usd: Option<f64>| usd.is_none_or(|usd| usd < min_liquidity_usd);
+            // Only pairs that started out below the floor count as new.
+            (below(state.first_liquidity_usd)
+                && below(state.liquidity_usd)
+                && liquidity >= min_liquidity_usd)
+                .then(|| {
+                    let message = format!(
+                        "new pair {pair} reached ${liquidity:.0} liquidity, above ${min_liquidity_usd:.0}"
+                    );
+                    (liquidity, min_liquidity_usd, message)
+                })
+        }
+    }
+}
@@ -0,0 +1,369 @@
+use alloy::primitives::{Address, U256};
+use eyre::Result;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::str::FromStr;
+
+use chain_model::{FilterReason, Pair, PriceTick};
+
+use crate::init::FilterConfig;
+use crate::price::to_units;
+
+/// Prices a pair needs before the deviation check applies.
+const MIN_MEDIAN_SAMPLES: usize = 3;
+
+/// The checks a tick must pass to be emitted, cheapest first.
+pub struct TickFilter {
+    verified_only: bool,
+    min_reserve_units: Option<f64>,
+    min_reserve_usd: Option<f64>,
+    max_deviation_percent: Option<f64>,
+    median_window: usize,
+    token_blocklist: HashSet<Address>,
+    token_allowlist: Option<HashSet<Address>>,
+    recent: HashMap<Address, VecDeque<f64>>,
+}
+
+impl TickFilter {
+    pub fn new(cfg: &FilterConfig) -> Result<Self> {
+        let parse = |list: &Vec<String>| -> Result<HashSet<Address>> {
+            list.iter()
+                .map(|a| Address::from_str(a).map_err(eyre::Report::from))
+                .collect()
+        };
+        Ok(Self {
+            verified_only: cfg.verified_only,
+            min_reserve_units: cfg.min_reserve_units,
+            min_reserve_usd: cfg.min_reserve_usd,
+            max_deviation_percent: cfg.max_deviation_percent,
+            median_window: cfg.median_window.max(1),
+            token_blocklist: cfg
+                .token_blocklist
+                .as_ref()
+                .map(parse)
+                .transpose()?
+                .unwrap_or_default(),
+            token_allowlist: cfg.token_allowlist.as_ref().map(parse).transpose()?,
+            recent: HashMap::new(),
+        })
+    }
+
+    /// Returns why `tick` is held back, or `None` if it passes.
+    pub fn check(&mut self, pair: &Pair, tick: &PriceTick) -> Option<(FilterReason, String)> {
+        let (token0, token1) = (pair.token0.address, pair.token1.address);
+
+        if tick.token0_reserve.is_zero() || tick.token1_reserve.is_zero() {
+            return Some((FilterReason::EmptyReserve, "a reserve is zero".to_string()));
+        }
+        if self.verified_only && !(pair.token0.verified && pair.token1.verified) {
+            return Some((
+                FilterReason::Unverified,
+                "a token is not on a token list".to_string(),
+            ));
+        }
+        if let Some(blocked) = [token0, token1]
+            .into_iter()
+            .find(|token| self.token_blocklist.contains(token))
+        {
+            return Some((FilterReason::BlockedToken, format!("{blocked} is blocked")));
+        }
+        if let Some(allowlist) = &self.token_allowlist {
+            if !allowlist.contains(&token0) && !allowlist.contains(&token1) {
+                return Some((
+                    FilterReason::NotAllowedToken,
+                    "no token is allowlisted".to_string(),
+                ));
+            }
+        }
+
+        let reserve0 = to_units(U256::from(tick.token0_reserve), pair.token0.decimals);
+        let reserve1 = to_units(U256::from(tick.token1_reserve), pair.token1.decimals);
+        if let Some(min) = self.min_reserve_units {
+            for (reserve, symbol) in [
+                (reserve0, &pair.token0.symbol),
+                (reserve1, &pair.token1.symbol),
+            ] {
+                if reserve < min {
+                    return Some((
+                        FilterReason::LowReserve,
+                        format!("{reserve} {symbol} below {min}"),
+                    ));
+                }
+            }
+        }
+        if let Some(min) = self.min_reserve_usd {
+            for (reserve, usd, symbol) in [
+                (reserve0, tick.token0_usd, &pair.token0.symbol),
+                (reserve1, tick.token1_usd, &pair.token1.symbol),
+            ] {
+                let Some(usd) = usd else { continue };
+                let value = reserve * usd;
+                if value < min {
+                    return Some((
+                        FilterReason::LowLiquidityUsd,
+                        format!("${value:.2} of {symbol} below ${min}"),
+                    ));
+                }
+            }
+        }
+
+        self.check_deviation(pair.address, tick.token0_token1)
+    }
+
+    /// Every price enters the window, held back or not, so the median follows
+    /// a lasting move after half a window.
+    fn check_deviation(&mut self, pair: Address, price: f64) -> Option<(FilterReason, String)> {
+        let max = self.max_deviation_percent?;
+        let recent = self.recent.entry(pair).or_default();
+
+        let verdict = median(recent)
+            .filter(|median| recent.len() >= MIN_MEDIAN_SAMPLES && *median > 0.0)
+            .and_then(|median| {
+                let deviation = (price - median).abs() / median * 100.0;
+                (deviation > max).then(|| {
+                    (
+                        FilterReason::Outlier,
+                        format!("{deviation:.2}% off the rolling median {median}"),
+                    )
+                })
+            });
+
+        recent.push_back(price);
+        if recent.len() > self.median_window {
+            recent.pop_front();
+        }
+        verdict
+    }
+}
+
+fn median(values: &VecDeque<f64>) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    let mut sorted: Vec<f64> = values.iter().copied().collect();
+    sorted.sort_by(f64::total_cmp);
+    let mid = sorted.len() / 2;
+    Some(if sorted.len().is_multiple_of(2) {
+        (sorted[mid - 1] + sorted[mid]) / 2.0
+    } else {
+        sorted[mid]
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::price::{price_tick, QuotePreference};
+    use alloy::primitives::{address, FixedBytes};
+    use chain_model::{SyncEvent, Token};
+
+    const WETH: Address = address!("C02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2");
+    const USDC: Address = address!("A0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48");
+
+    fn config() -> FilterConfig {
+        FilterConfig {
+            verified_only: false,
+            min_reserve_units: None,
+            min_reserve_usd: None,
+            max_deviation_percent: None,
+            median_window: 5,
+            token_blocklist: None,
+            token_allowlist: None,
+        }
+    }
+
+    fn pair(verified: bool) -> Pair {
+        let token = |address, symbol: &str, decimals| Token {
+            address,
+            decimals,
+            symbol: symbol.to_string(),
+            total_supply: U256::ZERO,
+            verified,
+            risk: None,
+        };
+        Pair {
+            address: address!("B4e16d0168e52d35CaCD2c6185b44281Ec28C9Dc"),
+            token0: token(USDC, "USDC", 6),
+            token1: token(WETH, "WETH", 18),
+            factory: None,
+            created_block: None,
+        }
+    }
+
+    /// A tick of `usdc` whole USDC against one WETH.
+    fn tick(pair: &Pair, usdc: u64, weth_wei: u128) -> PriceTick {
+        let event = SyncEvent {
+            pair: pair.address,
+            reserve0: U256::from(usdc * 1_000_000).to(),
+            reserve1: U256::from(weth_wei).to(),
+            transaction_hash: FixedBytes::ZERO,
+            log_index: Some(0),
+            block_number: 1,
+            block_timestamp: 0,
+            snapshot: false,
+        };
+        price_tick(&event, pair, 6, &QuotePreference::default()).unwrap()
+    }
+
+    const ONE_WETH: u128 = 1_000_000_000_000_000_000;
+
+    fn reason(filter: &mut TickFilter, pair: &Pair, tick: &PriceTick) -> Option<FilterReason> {
+        filter.check(pair, tick).map(|(reason, _)| reason)
+    }
+
+    #[test]
+    fn default_config_passes_any_liquid_tick() {
+        let mut filter = TickFilter::new(&config()).unwrap();
+        let pair = pair(false);
+        assert_eq!(
+            reason(&mut filter, &pair, &tick(&pair, 2500, ONE_WETH)),
+            None
+        );
+    }
+
+    #[test]
+    fn empty_reserves_are_held_back() {
+        let mut filter = TickFilter::new(&config()).unwrap();
+        let pair = pair(true);
+        assert_eq!(
+            reason(&mut filter, &pair, &tick(&pair, 2500, 0)),
+            Some(FilterReason::EmptyReserve)
+        );
+    }
+
+    #[test]
+    fn unverified_tokens_are_held_back_when_required() {
+        let mut filter = TickFilter::new(&FilterConfig {
+            verified_only: true,
+            ..config()
+        })
+        .unwrap();
+        let unverified = pair(false);
+        let verified = pair(true);
+        assert_eq!(
+            reason(&mut filter, &unverified, &tick(&unverified, 2500, ONE_WETH)),
+            Some(FilterReason::Unverified)
+        );
+        assert_eq!(
+            reason(&mut filter, &verified, &tick(&verified, 2500, ONE_WETH)),
+            None
+        );
+    }
+
+    #[test]
+    fn token_lists_apply_to_either_side() {
+        let pair = pair(true);
+        let tick = tick(&pair, 2500, ONE_WETH);
+
+        let mut blocked = TickFilter::new(&FilterConfig {
+            token_blocklist: Some(vec![WETH.to_string()]),
+            ..config()
+        })
+        .unwrap();
+        assert_eq!(
+            reason(&mut blocked, &pair, &tick),
+            Some(FilterReason::BlockedToken)
+        );
+
+        let mut allowed = TickFilter::new(&FilterConfig {
+            token_allowlist: Some(vec![USDC.to_string()]),
+            ..config()
+        })
+        .unwrap();
+        assert_eq!(reason(&mut allowed, &pair, &tick), None);
+
+        let mut not_allowed = TickFilter::new(&FilterConfig {
+            token_allowlist: Some(vec![Address::ZERO.to_string()]),
+            ..config()
+        })
+        .unwrap();
+        assert_eq!(
+            reason(&mut not_allowed, &pair, &tick),
+            Some(FilterReason::NotAllowedToken)
+        );
+    }
+
+    #[test]
+    fn reserves_are_compared_in_whole_tokens() {
+        let mut filter = TickFilter::new(&FilterConfig {
+            min_reserve_units: Some(1.0),
+            ..config()
+        })
+        .unwrap();
+        let pair = pair(true);
+        assert_eq!(
+            reason(&mut filter, &pair, &tick(&pair, 2500, ONE_WETH)),
+            None
+        );
+        assert_eq!(
+            reason(&mut filter, &pair, &tick(&pair, 2500, ONE_WETH / 2)),
+            Some(FilterReason::LowReserve)
+        );
+    }
+
+    #[test]
+    fn usd_liquidity_floor_skips_unpriced_sides() {
+        let mut filter = TickFilter::new(&FilterConfig {
+            min_reserve_usd: Some(1000.0),
+            ..config()
+        })
+        .unwrap();
+        let pair = pair(true);
+        let mut low = tick(&pair, 500, ONE_WETH);
+        assert_eq!(reason(&mut filter, &pair, &low), None);
+        low.token0_usd = Some(1.0);
+        assert_eq!(
+            reason(&mut filter, &pair, &low),
+            Some(FilterReason::LowLiquidityUsd)
+        );
+    }
+
+    #[test]
+    fn outliers_against_the_rolling_median_are_held_back() {
+        let mut filter = TickFilter::new(&FilterConfig {
+            max_deviation_percent: Some(10.0),
+            ..config()
+        })
+        .unwrap();
+        let pair = pair(true);
+        // Too few samples for a median yet: even a jump passes.
+        for usdc in [2500, 2510, 5000] {
+            assert_eq!(
+                reason(&mut filter, &pair, &tick(&pair, usdc, ONE_WETH)),
+                None
+            );
+        }
+        assert_eq!(
+            reason(&mut filter, &pair, &tick(&pair, 2490, ONE_WETH)),
+            None
+        );
+        assert_eq!(
+            reason(&mut filter, &pair, &tick(&pair, 25_000, ONE_WETH)),
+            Some(FilterReason::Outlier)
+        );
+    }
+
+    #[test]
+    fn a_lasting_move_passes_after_half_a_window() {
+        let mut filter = TickFilter::new(&FilterConfig {
+            max_deviation_percent: Some(10.0),
+            ..config()
+        })
+        .unwrap();
+        let pair = pair(true);
+        for _ in 0..5 {
+            filter.check(&pair, &tick(&pair, 2500, ONE_WETH));
+        }
+        let moved: Vec<_> = (0..4)
+            .map(|_| reason(&mut filter, &pair, &tick(&pair, 5000, ONE_WETH)))
+            .collect();
+        assert_eq!(
+            moved,
+            [
+                Some(FilterReason::Outlier),
+                Some(FilterReason::Outlier),
+                Some(FilterReason::Outlier),
+                None
+            ]
+        );
+    }
+}
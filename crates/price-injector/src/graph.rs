@@ -0,0 +1,308 @@
+use alloy::primitives::{Address, Uint, U256};
+use eyre::Result;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::str::FromStr;
+
+use chain_model::{Pair, SyncEvent, TokenPrice};
+
+use crate::init::GraphConfig;
+use crate::price::to_units;
+
+/// A tracked pair with its latest reserves in whole token units.
+#[derive(Debug)]
+struct Edge {
+    token0: Address,
+    token1: Address,
+    reserve0: f64,
+    reserve1: f64,
+}
+
+impl Edge {
+    fn other(&self, token: Address) -> Address {
+        if token == self.token0 {
+            self.token1
+        } else {
+            self.token0
+        }
+    }
+
+    /// Reserves as `(from side, other side)`.
+    fn reserves_from(&self, token: Address) -> (f64, f64) {
+        if token == self.token0 {
+            (self.reserve0, self.reserve1)
+        } else {
+            (self.reserve1, self.reserve0)
+        }
+    }
+}
+
+/// A token reached from the numeraire, `via` the pair leading to `parent`.
+#[derive(Debug, Clone)]
+struct Node {
+    price: f64,
+    liquidity: f64,
+    parent: Option<Address>,
+    via: Option<Address>,
+}
+
+#[derive(PartialEq)]
+struct Candidate {
+    liquidity: f64,
+    token: Address,
+}
+
+impl Eq for Candidate {}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.liquidity.total_cmp(&other.liquidity)
+    }
+}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Token graph over every pair seen on the Sync stream. Each token is priced
+/// in the numeraire through its widest path: the one whose thinnest pair holds
+/// the most liquidity, measured in the numeraire.
+///
+/// A reserve update only revisits the part of the price tree it can affect:
+/// the subtree hanging off the pair when it is on a chosen path, otherwise
+/// the paths that may now improve through it.
+pub struct TokenGraph {
+    numeraire: Address,
+    min_liquidity: f64,
+    edges: HashMap<Address, Edge>,
+    adjacent: HashMap<Address, Vec<Address>>,
+    symbols: HashMap<Address, String>,
+    nodes: HashMap<Address, Node>,
+    children: HashMap<Address, HashSet<Address>>,
+}
+
+impl TokenGraph {
+    pub fn new(cfg: &GraphConfig) -> Result<Self> {
+        let numeraire = Address::from_str(&cfg.numeraire)?;
+        let mut nodes = HashMap::new();
+        nodes.insert(
+            numeraire,
+            Node {
+                price: 1.0,
+                liquidity: f64::INFINITY,
+                parent: None,
+                via: None,
+            },
+        );
+        Ok(Self {
+            numeraire,
+            min_liquidity: cfg.min_liquidity,
+            edges: HashMap::new(),
+            adjacent: HashMap::new(),
+            symbols: HashMap::new(),
+            nodes,
+            children: HashMap::new(),
+        })
+    }
+
+    /// Applies the reserves of `event` and returns the tokens whose price
+    /// changed.
+    pub fn update(&mut self, pair: &Pair, event: &SyncEvent) -> Vec<TokenPrice> {
+        self.insert_edge(pair, event.reserve0, event.reserve1);
+
+        let child = [pair.token0.address, pair.token1.address]
+            .into_iter()
+            .find(|token| {
+                self.nodes
+                    .get(token)
+                    .is_some_and(|node| node.via == Some(pair.address))
+            });
+
+        let mut changed = HashSet::new();
+        let seeds = match child {
+            Some(child) => {
+                let detached = self.detach(child);
+                let seeds = self.boundary(&detached);
+                changed.extend(detached);
+                seeds
+            }
+            None => [pair.token0.address, pair.token1.address]
+                .into_iter()
+                .filter(|token| self.nodes.contains_key(token))
+                .collect(),
+        };
+        self.relax(seeds, &mut changed);
+
+        changed
+            .into_iter()
+            .filter_map(|token| self.token_price(token, event))
+            .collect()
+    }
+
+    /// Loads the last known reserves of many pairs and prices the whole tree
+    /// in one search, without reporting prices.
+    pub fn seed<'a>(
+        &mut self,
+        pairs: impl IntoIterator<Item = (&'a Pair, Uint<112, 2>, Uint<112, 2>)>,
+    ) {
+        for (pair, reserve0, reserve1) in pairs {
+            self.insert_edge(pair, reserve0, reserve1);
+        }
+        self.relax(vec![self.numeraire], &mut HashSet::new());
+    }
+
+    fn insert_edge(&mut self, pair: &Pair, reserve0: Uint<112, 2>, reserve1: Uint<112, 2>) {
+        let edge = Edge {
+            token0: pair.token0.address,
+            token1: pair.token1.address,
+            reserve0: to_units(U256::from(reserve0), pair.token0.decimals),
+            reserve1: to_units(U256::from(reserve1), pair.token1.decimals),
+        };
+        if self.edges.insert(pair.address, edge).is_none() {
+            for token in [&pair.token0, &pair.token1] {
+                self.adjacent
+                    .entry(token.address)
+                    .or_default()
+                    .push(pair.address);
+                self.symbols
+                    .entry(token.address)
+                    .or_insert_with(|| token.symbol.clone());
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Removes `root` and its subtree from the price tree.
+    fn detach(&mut self, root: Address) -> Vec<Address> {
+        let mut detached = Vec::new();
+        let mut stack = vec![root];
+        while let Some(token) = stack.pop() {
+            if let Some(node) = self.nodes.remove(&token) {
+                if let Some(parent) = node.parent {
+                    if let Some(siblings) = self.children.get_mut(&parent) {
+                        siblings.remove(&token);
+                    }
+                }
+            }
+            if let Some(children) = self.children.remove(&token) {
+                stack.extend(children);
+            }
+            detached.push(token);
+        }
+        detached
+    }
+
+    /// Priced neighbours of `tokens`, from which they can be reached again.
+    fn boundary(&self, tokens: &[Address]) -> Vec<Address> {
+        let mut seeds = HashSet::new();
+        for token in tokens {
+            for pair in self.adjacent.get(token).into_iter().flatten() {
+                let other = self.edges[pair].other(*token);
+                if self.nodes.contains_key(&other) {
+                    seeds.insert(other);
+                }
+            }
+        }
+        seeds.into_iter().collect()
+    }
+
+    /// Widest-path search starting from already priced `seeds`.
+    fn relax(&mut self, seeds: Vec<Address>, changed: &mut HashSet<Address>) {
+        let mut heap: BinaryHeap<Candidate> = seeds
+            .into_iter()
+            .map(|token| Candidate {
+                liquidity: self.nodes[&token].liquidity,
+                token,
+            })
+            .collect();
+
+        while let Some(Candidate { liquidity, token }) = heap.pop() {
+            let Some(node) = self.nodes.get(&token).cloned() else {
+                continue;
+            };
+            if liquidity < node.liquidity {
+                continue;
+            }
+
+            for pair in self.adjacent.get(&token).cloned().unwrap_or_default() {
+                let edge = &self.edges[&pair];
+                let next = edge.other(token);
+                if next == self.numeraire {
+                    continue;
+                }
+                let (reserve_from, reserve_next) = edge.reserves_from(token);
+                if reserve_from <= 0.0 || reserve_next <= 0.0 {
+                    continue;
+                }
+                let pair_liquidity = 2.0 * reserve_from * node.price;
+                if pair_liquidity < self.min_liquidity {
+                    continue;
+                }
+
+                let candidate = Node {
+                    price: node.price * reserve_from / reserve_next,
+                    liquidity: node.liquidity.min(pair_liquidity),
+                    parent: Some(token),
+                    via: Some(pair),
+                };
+                let accept = match self.nodes.get(&next) {
+                    None => true,
+                    // A child follows its parent even when the path got thinner.
+                    Some(current) if current.via == Some(pair) && current.parent == Some(token) => {
+                        current.price != candidate.price || current.liquidity != candidate.liquidity
+                    }
+                    Some(current) => candidate.liquidity > current.liquidity,
+                };
+                if !accept {
+                    continue;
+                }
+
+                self.attach(next, candidate.clone());
+                changed.insert(next);
+                heap.push(Candidate {
+                    liquidity: candidate.liquidity,
+                    token: next,
+                });
+            }
+        }
+    }
+
+    fn attach(&mut self, token: Address, node: Node) {
+        if let Some(previous) = self.nodes.get(&token).and_then(|n| n.parent) {
+            if let Some(siblings) = self.children.get_mut(&previous) {
+                siblings.remove(&token);
+            }
+        }
+        if let Some(parent) = node.parent {
+            self.children.entry(parent).or_default().insert(token);
+        }
+        self.nodes.insert(token, node);
+    }
+
+    fn token_price(&self, token: Address, event: &SyncEvent) -> Option<TokenPrice> {
+        let node = self.nodes.get(&token)?;
+
+        let mut path = Vec::new();
+        let mut cursor = node;
+        while let (Some(via), Some(parent)) = (cursor.via, cursor.parent) {
+            path.push(via.to_string());
+            cursor = &self.nodes[&parent];
+        }
+
+        Some(TokenPrice {
+            token_address: token.to_string(),
+            token_symbol: self.symbols.get(&token).cloned().unwrap_or_default(),
+            numeraire_address: self.numeraire.to_string(),
+            price: node.price,
+            liquidity: node.liquidity,
+            path,
+            block_number: event.block_number,
+            block_timestamp: event.block_timestamp,
+        })
+    }
+}
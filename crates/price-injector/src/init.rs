@@ -6,9 +6,16 @@ use std::path::Path;
 use tracing::debug;
 use tracing_subscriber::{fmt, prelude::*, EnvFilter};
 
+use crate::price::MAX_SCALE;
+
 #[derive(Debug, Deserialize)]
 pub struct AppConfig {
     pub nats: NatsConfig,
+    pub filter: FilterConfig,
+    pub price: PriceConfig,
+    pub pending: PendingConfig,
+    pub usd: Option<UsdConfig>,
+    pub graph: Option<GraphConfig>,
     pub log: Option<LogConfig>,
 }
 
@@ -17,10 +24,67 @@ pub struct NatsConfig {
     pub server_url: String,
     pub subject_input: String,
     pub kv_bucket: String,
+    /// Bucket holding the latest emitted `PriceTick` per pair.
+    pub state_bucket: Option<String>,
     pub subject_output: String,
+    /// Subject of the `FilteredTick`s held back by `[filter]`.
+    pub subject_filtered: String,
     pub stream_name: String,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct FilterConfig {
+    /// Only emit ticks whose tokens are both on a curated token list.
+    pub verified_only: bool,
+    /// Minimum reserve of each side, in whole tokens.
+    pub min_reserve_units: Option<f64>,
+    /// Minimum value of each side in USD; sides without a USD price pass.
+    pub min_reserve_usd: Option<f64>,
+    /// Maximum deviation of a price from the pair's rolling median, in percent.
+    pub max_deviation_percent: Option<f64>,
+    /// Number of recent prices per pair the median is taken over.
+    pub median_window: usize,
+    /// Drop pairs with any token in this list.
+    pub token_blocklist: Option<Vec<String>>,
+    /// Keep only pairs with at least one token in this list.
+    pub token_allowlist: Option<Vec<String>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PriceConfig {
+    /// Fractional digits of the exact decimal prices, at most `MAX_SCALE`.
+    pub scale: u32,
+    /// Quote assets in order of preference, e.g. USDC, USDT, DAI, WETH, WBTC.
+    pub quote_preference: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PendingConfig {
+    /// Redelivery delay for Sync events whose pair is not in the KV bucket yet.
+    pub retry_delay_ms: u64,
+    /// Age after which such events are dropped as permanently unmatched.
+    pub max_wait_secs: u64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UsdConfig {
+    /// Tokens priced at exactly $1.
+    pub stablecoins: Vec<String>,
+    /// Pairs whose Sync events re-anchor the USD price of their non-stable
+    /// side, e.g. WETH/USDC, then WBTC/WETH.
+    pub reference_pairs: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GraphConfig {
+    /// Token every other token is priced in.
+    pub numeraire: String,
+    /// Pairs holding less than this, in numeraire units, are not traversed.
+    pub min_liquidity: f64,
+    /// Subject of the per-token `TokenPrice` updates.
+    pub subject_output: String,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct LogConfig {
     pub level: String,
@@ -36,9 +100,23 @@ struct Cli {
     #[arg(long)]
     subject_output: Option<String>,
     #[arg(long)]
+    subject_filtered: Option<String>,
+    #[arg(long)]
     kv_bucket: Option<String>,
     #[arg(long)]
+    state_bucket: Option<String>,
+    #[arg(long)]
     stream_name: Option<String>,
+    #[arg(long)]
+    verified_only: Option<bool>,
+    #[arg(long)]
+    min_reserve_units: Option<f64>,
+    #[arg(long)]
+    min_reserve_usd: Option<f64>,
+    #[arg(long)]
+    max_deviation_percent: Option<f64>,
+    #[arg(long)]
+    price_scale: Option<u32>,
 }
 
 impl AppConfig {
@@ -48,16 +126,35 @@ impl AppConfig {
         let config_path2 = Path::new("price-injector.toml");
 
         let cfg: AppConfig = Config::builder()
+            .set_default("nats.subject_filtered", "eth.univ2.pair.filtered.1")?
+            .set_default("filter.verified_only", false)?
+            .set_default("filter.median_window", 20)?
+            .set_default("price.scale", 36)?
+            .set_default("price.quote_preference", Vec::<String>::new())?
+            .set_default("pending.retry_delay_ms", 1000)?
+            .set_default("pending.max_wait_secs", 120)?
             .add_source(File::from(config_path1).required(false))
             .add_source(File::from(config_path2).required(false))
             .set_override_option("nats.server_url", cli.server_url)?
             .set_override_option("nats.subject_input", cli.subject_input)?
             .set_override_option("nats.subject_output", cli.subject_output)?
+            .set_override_option("nats.subject_filtered", cli.subject_filtered)?
             .set_override_option("nats.kv_bucket", cli.kv_bucket)?
+            .set_override_option("nats.state_bucket", cli.state_bucket)?
             .set_override_option("nats.stream_name", cli.stream_name)?
+            .set_override_option("filter.verified_only", cli.verified_only)?
+            .set_override_option("filter.min_reserve_units", cli.min_reserve_units)?
+            .set_override_option("filter.min_reserve_usd", cli.min_reserve_usd)?
+            .set_override_option("filter.max_deviation_percent", cli.max_deviation_percent)?
+            .set_override_option("price.scale", cli.price_scale)?
             .build()
             .map_err(eyre::Report::from)?
             .try_deserialize()?;
+        eyre::ensure!(
+            cfg.price.scale <= MAX_SCALE,
+            "price.scale {} exceeds {MAX_SCALE}",
+            cfg.price.scale
+        );
         Ok(cfg)
     }
 
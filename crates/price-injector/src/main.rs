@@ -1,13 +1,21 @@
-use alloy::primitives::U256;
-use eyre::{eyre, Ok, Result};
+use alloy::primitives::Address;
+use eyre::{eyre, Result};
 use futures_util::StreamExt;
-use std::str::from_utf8;
-use tracing::{info, warn};
+use std::str::FromStr;
+use std::time::Duration;
+use tracing::{debug, info, warn};
 
-use chain_model::{Pair, PriceTick, SyncEvent};
+use chain_model::{FilteredTick, SyncEvent};
 
+mod cache;
+mod filter;
+mod graph;
 mod init;
 mod mq;
+mod pending;
+mod price;
+mod state;
+mod usd;
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -24,6 +32,26 @@ async fn main() -> Result<()> {
     .await?;
 
     let kv = mq_client.kv_store(&app_cfg.nats.kv_bucket).await?;
+    let pairs = cache::PairCache::start(&kv).await?;
+    let mut state = match &app_cfg.nats.state_bucket {
+        Some(bucket) => Some(state::StateStore::new(mq_client.kv_store(bucket).await?)),
+        None => None,
+    };
+    let quotes = price::QuotePreference::new(&app_cfg.price.quote_preference)?;
+    let mut usd = usd::UsdPricer::new(app_cfg.usd.as_ref())?;
+    let mut filter = filter::TickFilter::new(&app_cfg.filter)?;
+    let mut graph = app_cfg
+        .graph
+        .as_ref()
+        .map(|cfg| graph::TokenGraph::new(cfg).map(|graph| (graph, &cfg.subject_output)))
+        .transpose()?;
+    if let Some((graph, _)) = graph.as_mut() {
+        seed_graph(graph, &pairs, state.as_mut()).await?;
+    }
+    let mut pending = pending::PendingPolicy::new(
+        Duration::from_millis(app_cfg.pending.retry_delay_ms),
+        Duration::from_secs(app_cfg.pending.max_wait_secs),
+    );
 
     let mut sub = mq_client.jetstream_pull_from(true).await?;
     while let Some(msg_result) = sub.next().await {
@@ -31,71 +59,63 @@ async fn main() -> Result<()> {
 
         let event: SyncEvent = serde_json::from_slice(&msg.payload)?;
 
-        let entry = kv.entry(event.pair.to_string()).await?;
-        if let Some(entry) = entry {
-            info!(
-                "{} @ {} -> {}",
-                entry.key,
-                entry.revision,
-                from_utf8(&entry.value)?
-            );
-
-            let pair: Pair = serde_json::from_slice(&entry.value)?;
-
-            let reserve0 = U256::from(event.reserve0);
-            let reserve1 = U256::from(event.reserve1);
-
-            if reserve0.is_zero() {
-                warn!("reserve0 is zero, skip");
+        let Some((pair, revision)) = pairs.get(&event.pair) else {
+            pending.defer(&msg, event.pair).await?;
+            continue;
+        };
+        debug!("{} @ {} -> {:?}", event.pair, revision, pair);
+
+        // A token reports its own decimals; one that cannot be priced must not
+        // stall the stream.
+        let mut price_msg = match price::price_tick(&event, &pair, app_cfg.price.scale, &quotes) {
+            Ok(tick) => tick,
+            Err(e) => {
+                warn!("skipping unpriceable sync of {}: {e}", pair.address);
                 msg.ack()
                     .await
                     .map_err(|e| eyre!("ack message failed: {e}"))?;
                 continue;
             }
-
-            // Calculate price with adjusted decimals using U256 to avoid precision loss.
-            let scaling_factor = U256::from(10_u64).pow(U256::from(18_u64));
-            let decimal_diff = pair.token0.decimals as i32 - pair.token1.decimals as i32;
-            // 10^|decimal_diff|
-            let adjustment = U256::from(10u64).pow(U256::from(decimal_diff.unsigned_abs() as u32));
-
-            let token1_token0_precise = if decimal_diff >= 0 {
-                (reserve1 * adjustment * scaling_factor) / reserve0
-            } else {
-                (reserve1 * scaling_factor) / (reserve0 * adjustment)
+        };
+        // Priced with the current anchors for the USD liquidity floor.
+        usd.apply(&pair, &mut price_msg);
+
+        if let Some((reason, detail)) = filter.check(&pair, &price_msg) {
+            info!("filtered tick of {}: {detail}", pair.address);
+            let filtered = FilteredTick {
+                reason,
+                detail,
+                tick: price_msg,
             };
+            let payload = serde_json::to_string(&filtered)?;
+            mq_client
+                .produce_record_to(&app_cfg.nats.subject_filtered, payload)
+                .await?;
+            msg.ack()
+                .await
+                .map_err(|e| eyre!("ack message failed: {e}"))?;
+            continue;
+        }
 
-            // For quick, less-precise views, convert to f64 at the end.
-            let token0_token1 = token1_token0_precise.to_string().parse::<f64>()? / 1e18;
-
-            let token1_token0 = if token0_token1 == 0.0 {
-                0.0
-            } else {
-                1.0 / token0_token1
-            };
-
-            let price_msg = PriceTick {
-                pair_address: event.pair.to_string(),
-
-                token0_address: pair.token0.address.to_string(),
-                token0_reserve: event.reserve0,
-                token0_symbol: pair.token0.symbol,
-
-                token1_address: pair.token1.address.to_string(),
-                token1_reserve: event.reserve1,
-                token1_symbol: pair.token1.symbol,
-
-                token0_token1,
-                token1_token0,
-
-                transaction_hash: event.transaction_hash.to_string(),
-                block_number: event.block_number,
-                block_timestamp: event.block_timestamp,
-            };
+        // Only ticks that passed the filter move the anchors; a reference
+        // pair's own tick is then repriced with the anchor it just set.
+        usd.update(&pair, &price_msg);
+        usd.apply(&pair, &mut price_msg);
+
+        // Like the anchors, the graph only learns reserves that passed the filter.
+        if let Some((graph, subject)) = graph.as_mut() {
+            for token_price in graph.update(&pair, &event) {
+                debug!("token price: {token_price:?}");
+                let payload = serde_json::to_string(&token_price)?;
+                mq_client.produce_record_to(subject, payload).await?;
+            }
+        }
 
-            let payload = serde_json::to_string(&price_msg)?;
-            mq_client.produce_record(payload).await?;
-            info!("price msg: {price_msg:?}");
+        let payload = serde_json::to_string(&price_msg)?;
+        mq_client.produce_record(payload).await?;
+        info!("price msg: {price_msg:?}");
+        if let Some(state) = state.as_mut() {
+            state.write(&price_msg).await?;
         }
         msg.ack()
             .await
@@ -104,3 +124,38 @@ async fn main() -> Result<()> {
 
     Ok(())
 }
+
+/// Starts the graph from every tracked pair with a tick in the state bucket,
+/// so paths through pairs that have not synced since the restart exist.
+/// Pairs whose ticks were all filtered join once one of their ticks passes.
+async fn seed_graph(
+    graph: &mut graph::TokenGraph,
+    pairs: &cache::PairCache,
+    state: Option<&mut state::StateStore>,
+) -> Result<()> {
+    let Some(state) = state else {
+        warn!("no nats.state_bucket, token graph starts empty");
+        return Ok(());
+    };
+
+    let mut seeds = Vec::new();
+    for tick in state.load_all().await? {
+        let Some(address) = Address::from_str(&tick.pair_address).ok() else {
+            continue;
+        };
+        if let Some((pair, _)) = pairs.get(&address) {
+            seeds.push((pair, tick.token0_reserve, tick.token1_reserve));
+        }
+    }
+    graph.seed(
+        seeds
+            .iter()
+            .map(|(pair, reserve0, reserve1)| (pair, *reserve0, *reserve1)),
+    );
+    info!(
+        "seeded token graph from {} pairs, {} tokens priced",
+        seeds.len(),
+        graph.len()
+    );
+    Ok(())
+}
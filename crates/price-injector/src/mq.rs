@@ -31,8 +31,12 @@ impl MqClient {
     }
 
     pub async fn produce_record(&self, record: String) -> Result<()> {
+        self.produce_record_to(&self.subject_output, record).await
+    }
+
+    pub async fn produce_record_to(&self, subject: &str, record: String) -> Result<()> {
         self.nats
-            .publish(self.subject_output.clone(), record.into())
+            .publish(subject.to_string(), record.into())
             .await
             .map_err(|e| eyre::eyre!("NATS publish failed: {}", e))?;
         Ok(())
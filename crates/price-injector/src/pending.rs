@@ -0,0 +1,56 @@
+use alloy::primitives::Address;
+use async_nats::jetstream::{AckKind, Message};
+use eyre::{eyre, Result};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{debug, warn};
+
+/// Handles Sync events that arrive before `pair-enricher` has stored their
+/// pair: they are redelivered after `retry_delay` until `max_wait` has passed
+/// since they were published, then dropped and counted.
+pub struct PendingPolicy {
+    retry_delay: Duration,
+    max_wait: Duration,
+    unmatched: u64,
+}
+
+impl PendingPolicy {
+    pub fn new(retry_delay: Duration, max_wait: Duration) -> Self {
+        Self {
+            retry_delay,
+            max_wait,
+            unmatched: 0,
+        }
+    }
+
+    /// Naks `msg` with a delay, or terminates it once it is older than
+    /// `max_wait`. A redelivered event may be processed after newer events of
+    /// the same pair.
+    pub async fn defer(&mut self, msg: &Message, pair: Address) -> Result<()> {
+        let waited = message_age(msg)?;
+        let ack = if waited >= self.max_wait {
+            self.unmatched += 1;
+            warn!(
+                "pair {pair} still unknown after {}s, dropping sync event ({} unmatched in total)",
+                waited.as_secs(),
+                self.unmatched
+            );
+            AckKind::Term
+        } else {
+            debug!(
+                "pair {pair} not enriched yet, retry in {:?}",
+                self.retry_delay
+            );
+            AckKind::Nak(Some(self.retry_delay))
+        };
+        msg.ack_with(ack)
+            .await
+            .map_err(|e| eyre!("ack message failed: {e}"))
+    }
+}
+
+fn message_age(msg: &Message) -> Result<Duration> {
+    let info = msg.info().map_err(|e| eyre!("message info: {e}"))?;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let published = info.published.unix_timestamp().max(0) as u64;
+    Ok(Duration::from_secs(now.saturating_sub(published)))
+}
@@ -0,0 +1,316 @@
+use alloy::primitives::{Address, U256, U512};
+use eyre::{eyre, Result};
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use chain_model::{Pair, PriceTick, SyncEvent};
+
+/// Largest `price.scale`. Exact prices of tokens with up to 43 decimals stay
+/// within U512 at this scale; a token reporting more yields an error instead.
+pub const MAX_SCALE: u32 = 77;
+
+/// Ranked quote assets, e.g. USDC, USDT, DAI, WETH, WBTC.
+#[derive(Debug, Default)]
+pub struct QuotePreference {
+    ranks: HashMap<Address, usize>,
+}
+
+impl QuotePreference {
+    pub fn new(quotes: &[String]) -> Result<Self> {
+        let ranks = quotes
+            .iter()
+            .enumerate()
+            .map(|(rank, address)| Ok((Address::from_str(address)?, rank)))
+            .collect::<Result<_>>()?;
+        Ok(Self { ranks })
+    }
+
+    /// Whether token0 is the quote side: the token ranked first wins, and
+    /// pairs with neither token listed keep token1 as the quote.
+    pub fn quote_is_token0(&self, pair: &Pair) -> bool {
+        match (
+            self.ranks.get(&pair.token0.address),
+            self.ranks.get(&pair.token1.address),
+        ) {
+            (Some(rank0), Some(rank1)) => rank0 < rank1,
+            (Some(_), None) => true,
+            _ => false,
+        }
+    }
+}
+
+/// Builds the tick for `event`. Exact fixed-point prices are computed first;
+/// the f64 fields are derived from them for quick, less-precise views.
+pub fn price_tick(
+    event: &SyncEvent,
+    pair: &Pair,
+    scale: u32,
+    quotes: &QuotePreference,
+) -> Result<PriceTick> {
+    let reserve0 = U256::from(event.reserve0);
+    let reserve1 = U256::from(event.reserve1);
+
+    let token0_token1_exact = exact_price(
+        reserve0,
+        pair.token0.decimals,
+        reserve1,
+        pair.token1.decimals,
+        scale,
+    )?;
+    let token1_token0_exact = exact_price(
+        reserve1,
+        pair.token1.decimals,
+        reserve0,
+        pair.token0.decimals,
+        scale,
+    )?;
+
+    let (base, quote, price_exact) = if quotes.quote_is_token0(pair) {
+        (&pair.token1, &pair.token0, token1_token0_exact.clone())
+    } else {
+        (&pair.token0, &pair.token1, token0_token1_exact.clone())
+    };
+
+    Ok(PriceTick {
+        pair_address: event.pair.to_string(),
+
+        token0_address: pair.token0.address.to_string(),
+        token0_reserve: event.reserve0,
+        token0_symbol: pair.token0.symbol.clone(),
+
+        token1_address: pair.token1.address.to_string(),
+        token1_reserve: event.reserve1,
+        token1_symbol: pair.token1.symbol.clone(),
+
+        token0_verified: pair.token0.verified,
+        token1_verified: pair.token1.verified,
+
+        token0_token1: token0_token1_exact.parse::<f64>()?,
+        token1_token0: token1_token0_exact.parse::<f64>()?,
+        token0_token1_exact,
+        token1_token0_exact,
+
+        base_address: base.address.to_string(),
+        base_symbol: base.symbol.clone(),
+        quote_address: quote.address.to_string(),
+        quote_symbol: quote.symbol.clone(),
+        price: price_exact.parse::<f64>()?,
+        price_exact,
+
+        token0_usd: None,
+        token1_usd: None,
+        liquidity_usd: None,
+        usd_route: None,
+
+        transaction_hash: event.transaction_hash.to_string(),
+        log_index: event.log_index,
+        block_number: event.block_number,
+        block_timestamp: event.block_timestamp,
+    })
+}
+
+/// Converts a raw token amount to whole token units.
+pub fn to_units(amount: U256, decimals: u8) -> f64 {
+    f64::from(amount) / 10f64.powi(decimals as i32)
+}
+
+/// Exact price of one whole `base` token in `quote` tokens as a fixed-point
+/// decimal string with `scale` fractional digits, rounded down:
+///
+/// `(reserve_quote / 10^quote_decimals) / (reserve_base / 10^base_decimals)`
+///
+/// An empty pool side (`reserve_base` is zero) is priced at zero.
+pub fn exact_price(
+    reserve_base: U256,
+    base_decimals: u8,
+    reserve_quote: U256,
+    quote_decimals: u8,
+    scale: u32,
+) -> Result<String> {
+    if reserve_base.is_zero() {
+        return Ok(to_fixed(U512::ZERO, scale));
+    }
+
+    // Reserves are uint112, but a token reports its own decimals, so a large
+    // `base_decimals + scale` can still overflow U512.
+    let numerator = U512::from(reserve_quote)
+        .checked_mul(pow10(base_decimals as u32 + scale)?)
+        .ok_or_else(|| eyre!("price numerator overflow"))?;
+    let denominator = U512::from(reserve_base)
+        .checked_mul(pow10(quote_decimals as u32)?)
+        .ok_or_else(|| eyre!("price denominator overflow"))?;
+
+    Ok(to_fixed(numerator / denominator, scale))
+}
+
+fn pow10(exp: u32) -> Result<U512> {
+    U512::from(10u64)
+        .checked_pow(U512::from(exp))
+        .ok_or_else(|| eyre!("10^{exp} overflows"))
+}
+
+/// Formats `value / 10^scale` with exactly `scale` fractional digits.
+fn to_fixed(value: U512, scale: u32) -> String {
+    let digits = value.to_string();
+    let scale = scale as usize;
+    if scale == 0 {
+        return digits;
+    }
+    let padded = format!("{digits:0>width$}", width = scale + 1);
+    let (int_part, frac_part) = padded.split_at(padded.len() - scale);
+    format!("{int_part}.{frac_part}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy::primitives::{address, FixedBytes};
+    use chain_model::Token;
+
+    const WETH: Address = address!("C02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2");
+    const USDC: Address = address!("A0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48");
+    const PEPE: Address = address!("6982508145454Ce325dDbE47a25d4ec3d2311933");
+
+    fn units(amount: u64, decimals: u8) -> U256 {
+        U256::from(amount) * U256::from(10).pow(U256::from(decimals))
+    }
+
+    #[test]
+    fn exact_price_scales_both_decimals() {
+        // 1 WETH against 2500 USDC.
+        let weth = units(1, 18);
+        let usdc = units(2500, 6);
+        assert_eq!(
+            exact_price(weth, 18, usdc, 6, 18).unwrap(),
+            "2500.000000000000000000"
+        );
+        assert_eq!(
+            exact_price(usdc, 6, weth, 18, 18).unwrap(),
+            "0.000400000000000000"
+        );
+    }
+
+    #[test]
+    fn exact_price_rounds_down() {
+        let price = exact_price(U256::from(3), 0, U256::from(1), 0, 6).unwrap();
+        assert_eq!(price, "0.333333");
+        let price = exact_price(U256::from(3), 0, U256::from(2), 0, 6).unwrap();
+        assert_eq!(price, "0.666666");
+    }
+
+    #[test]
+    fn exact_price_keeps_the_scale() {
+        assert_eq!(
+            exact_price(U256::ZERO, 18, units(5, 18), 18, 4).unwrap(),
+            "0.0000"
+        );
+        assert_eq!(
+            exact_price(units(2, 18), 18, units(5, 18), 18, 0).unwrap(),
+            "2"
+        );
+        assert_eq!(
+            exact_price(U256::from(1), 0, U256::from(7), 0, 2).unwrap(),
+            "7.00"
+        );
+    }
+
+    #[test]
+    fn exact_price_stays_exact_beyond_f64() {
+        let price = exact_price(U256::from(1), 0, U256::from(u64::MAX), 0, 1).unwrap();
+        assert_eq!(price, "18446744073709551615.0");
+    }
+
+    #[test]
+    fn exact_price_fits_max_scale() {
+        let max_reserve = U256::from(2).pow(U256::from(112)) - U256::from(1);
+        assert!(exact_price(U256::from(1), 43, max_reserve, 0, MAX_SCALE).is_ok());
+        assert!(exact_price(U256::from(1), 255, max_reserve, 0, MAX_SCALE).is_err());
+    }
+
+    fn token(address: Address, symbol: &str, decimals: u8) -> Token {
+        Token {
+            address,
+            decimals,
+            symbol: symbol.to_string(),
+            total_supply: U256::ZERO,
+            verified: true,
+            risk: None,
+        }
+    }
+
+    /// A pair holding 1 token0 for 2500 token1, both in 18 decimals.
+    fn tick(token0: Token, token1: Token, quotes: &[Address]) -> PriceTick {
+        let pair = Pair {
+            address: address!("B4e16d0168e52d35CaCD2c6185b44281Ec28C9Dc"),
+            token0,
+            token1,
+            factory: None,
+            created_block: None,
+        };
+        let event = SyncEvent {
+            pair: pair.address,
+            reserve0: "1000000000000000000".parse().unwrap(),
+            reserve1: "2500000000000000000000".parse().unwrap(),
+            transaction_hash: FixedBytes::ZERO,
+            log_index: Some(0),
+            block_number: 1,
+            block_timestamp: 0,
+            snapshot: false,
+        };
+        let quotes = quotes.iter().map(|q| q.to_string()).collect::<Vec<_>>();
+        price_tick(&event, &pair, 6, &QuotePreference::new(&quotes).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn token1_quote_keeps_the_pair_orientation() {
+        let tick = tick(token(WETH, "WETH", 18), token(USDC, "USDC", 18), &[USDC]);
+        assert_eq!(
+            (tick.base_symbol.as_str(), tick.quote_symbol.as_str()),
+            ("WETH", "USDC")
+        );
+        assert_eq!(tick.price_exact, "2500.000000");
+        assert_eq!(tick.price_exact, tick.token0_token1_exact);
+        assert_eq!(tick.price, 2500.0);
+    }
+
+    #[test]
+    fn token0_quote_flips_the_price() {
+        let tick = tick(token(USDC, "USDC", 18), token(WETH, "WETH", 18), &[USDC]);
+        assert_eq!(
+            (tick.base_symbol.as_str(), tick.quote_symbol.as_str()),
+            ("WETH", "USDC")
+        );
+        assert_eq!(tick.base_address, WETH.to_string());
+        assert_eq!(tick.price_exact, "0.000400");
+        assert_eq!(tick.price_exact, tick.token1_token0_exact);
+    }
+
+    #[test]
+    fn the_higher_ranked_quote_wins() {
+        let oriented = tick(
+            token(WETH, "WETH", 18),
+            token(USDC, "USDC", 18),
+            &[WETH, USDC],
+        );
+        assert_eq!(oriented.quote_symbol, "WETH");
+        assert_eq!(oriented.price_exact, "0.000400");
+
+        let oriented = tick(
+            token(WETH, "WETH", 18),
+            token(USDC, "USDC", 18),
+            &[USDC, WETH],
+        );
+        assert_eq!(oriented.quote_symbol, "USDC");
+        assert_eq!(oriented.price_exact, "2500.000000");
+    }
+
+    #[test]
+    fn unlisted_pairs_quote_in_token1() {
+        let tick = tick(token(PEPE, "PEPE", 18), token(USDC, "USDC", 18), &[WETH]);
+        assert_eq!(
+            (tick.base_symbol.as_str(), tick.quote_symbol.as_str()),
+            ("PEPE", "USDC")
+        );
+        assert_eq!(tick.price_exact, tick.token0_token1_exact);
+    }
+}
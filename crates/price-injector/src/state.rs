@@ -0,0 +1,190 @@
+use async_nats::jetstream::kv::{CreateErrorKind, Operation, Store, UpdateErrorKind};
+use eyre::{eyre, Result};
+use futures_util::StreamExt;
+use std::collections::HashMap;
+use tracing::debug;
+
+use chain_model::PriceTick;
+
+/// Attempts of one write when other writers keep winning the race.
+const MAX_ATTEMPTS: usize = 5;
+
+/// Where a tick's `Sync` sits in the chain: block number, then log index.
+/// Ticks without a log index order before those with one in the same block.
+type Position = (u64, Option<u64>);
+
+fn position(tick: &PriceTick) -> Position {
+    (tick.block_number, tick.log_index)
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Known {
+    revision: u64,
+    position: Position,
+}
+
+impl Known {
+    /// Whether the stored tick comes after `tick`, so `tick` must not replace it.
+    fn is_newer_than(&self, tick: &PriceTick) -> bool {
+        self.position > position(tick)
+    }
+}
+
+/// Latest emitted `PriceTick` per pair in a KV bucket, for point-in-time
+/// lookups. Writes are compare-and-set on the key revision, so a tick of an
+/// older `Sync` never replaces a newer one, whichever instance writes it.
+pub struct StateStore {
+    kv: Store,
+    known: HashMap<String, Known>,
+}
+
+impl StateStore {
+    pub fn new(kv: Store) -> Self {
+        Self {
+            kv,
+            known: HashMap::new(),
+        }
+    }
+
+    /// Returns `false` if the bucket already holds a newer `Sync`.
+    pub async fn write(&mut self, tick: &PriceTick) -> Result<bool> {
+        let key = tick.pair_address.clone();
+        let value = serde_json::to_vec(tick)?;
+
+        for _ in 0..MAX_ATTEMPTS {
+            let known = match self.known.get(&key) {
+                Some(known) => Some(*known),
+                None => self.load(&key).await?,
+            };
+            if known.is_some_and(|known| known.is_newer_than(tick)) {
+                debug!(
+                    "state of {key} is newer than block {} log {:?}",
+                    tick.block_number, tick.log_index
+                );
+                return Ok(false);
+            }
+
+            let written = match known {
+                Some(known) => match self
+                    .kv
+                    .update(&key, value.clone().into(), known.revision)
+                    .await
+                {
+                    Ok(revision) => Some(revision),
+                    Err(e) if e.kind() == UpdateErrorKind::WrongLastRevision => None,
+                    Err(e) => return Err(eyre!("state update of {key} failed: {e}")),
+                },
+                None => match self.kv.create(&key, value.clone().into()).await {
+                    Ok(revision) => Some(revision),
+                    Err(e) if e.kind() == CreateErrorKind::AlreadyExists => None,
+                    Err(e) => return Err(eyre!("state create of {key} failed: {e}")),
+                },
+            };
+
+            match written {
+                Some(revision) => {
+                    self.known.insert(
+                        key,
+                        Known {
+                            revision,
+                            position: position(tick),
+                        },
+                    );
+                    return Ok(true);
+                }
+                // Someone else wrote the key since; re-read and compare again.
+                None => {
+                    self.known.remove(&key);
+                }
+            }
+        }
+        Err(eyre!("state of {key} kept changing, gave up"))
+    }
+
+    /// Every stored tick, e.g. to rebuild in-memory state after a restart.
+    pub async fn load_all(&mut self) -> Result<Vec<PriceTick>> {
+        let mut keys = Vec::new();
+        let mut stream = self.kv.keys().await?;
+        while let Some(key) = stream.next().await {
+            keys.push(key?);
+        }
+
+        let mut ticks = Vec::with_capacity(keys.len());
+        for key in keys {
+            if let Some((_, tick)) = self.read(&key).await? {
+                ticks.push(tick);
+            }
+        }
+        Ok(ticks)
+    }
+
+    async fn load(&mut self, key: &str) -> Result<Option<Known>> {
+        Ok(self.read(key).await?.map(|(known, _)| known))
+    }
+
+    async fn read(&mut self, key: &str) -> Result<Option<(Known, PriceTick)>> {
+        let Some(entry) = self.kv.entry(key).await? else {
+            return Ok(None);
+        };
+        if entry.operation != Operation::Put {
+            return Ok(None);
+        }
+        let stored: PriceTick = serde_json::from_slice(&entry.value)?;
+        let known = Known {
+            revision: entry.revision,
+            position: position(&stored),
+        };
+        self.known.insert(key.to_string(), known);
+        Ok(Some((known, stored)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tick(block_number: u64, log_index: Option<u64>) -> PriceTick {
+        serde_json::from_value(serde_json::json!({
+            "pair_address": "0xB4e16d0168e52d35CaCD2c6185b44281Ec28C9Dc",
+            "token0_address": "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48",
+            "token0_reserve": "0x1",
+            "token0_symbol": "USDC",
+            "token1_address": "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2",
+            "token1_reserve": "0x1",
+            "token1_symbol": "WETH",
+            "token0_token1": 1.0,
+            "token1_token0": 1.0,
+            "transaction_hash": "0x00",
+            "log_index": log_index,
+            "block_number": block_number,
+            "block_timestamp": 0,
+        }))
+        .unwrap()
+    }
+
+    fn known(block_number: u64, log_index: Option<u64>) -> Known {
+        Known {
+            revision: 1,
+            position: position(&tick(block_number, log_index)),
+        }
+    }
+
+    #[test]
+    fn older_blocks_never_replace_newer_ones() {
+        assert!(known(100, Some(0)).is_newer_than(&tick(99, Some(7))));
+        assert!(!known(100, Some(7)).is_newer_than(&tick(101, Some(0))));
+    }
+
+    #[test]
+    fn later_logs_of_the_same_block_win() {
+        assert!(known(100, Some(5)).is_newer_than(&tick(100, Some(2))));
+        assert!(!known(100, Some(2)).is_newer_than(&tick(100, Some(5))));
+        assert!(known(100, Some(0)).is_newer_than(&tick(100, None)));
+    }
+
+    #[test]
+    fn redelivered_ticks_are_rewritten() {
+        assert!(!known(100, Some(5)).is_newer_than(&tick(100, Some(5))));
+        assert!(!known(100, None).is_newer_than(&tick(100, None)));
+    }
+}
@@ -0,0 +1,137 @@
+use alloy::primitives::{Address, U256};
+use eyre::Result;
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+use tracing::debug;
+
+use chain_model::{Pair, PriceTick};
+
+use crate::init::UsdConfig;
+use crate::price::to_units;
+
+const STABLE_ROUTE: &str = "stable";
+
+#[derive(Debug, Clone)]
+struct UsdPrice {
+    usd: f64,
+    route: String,
+    /// Reference pair this price was derived from, `None` for stablecoins.
+    source: Option<Address>,
+}
+
+/// Live USD prices anchored on stablecoins (fixed at $1) and propagated
+/// through the configured reference pairs, e.g. WETH/USDC then WBTC/WETH.
+#[derive(Debug, Default)]
+pub struct UsdPricer {
+    stablecoins: HashSet<Address>,
+    reference_pairs: HashSet<Address>,
+    anchored: HashMap<Address, UsdPrice>,
+}
+
+impl UsdPricer {
+    pub fn new(cfg: Option<&UsdConfig>) -> Result<Self> {
+        let Some(cfg) = cfg else {
+            return Ok(Self::default());
+        };
+        let parse = |list: &[String]| -> Result<HashSet<Address>> {
+            list.iter()
+                .map(|a| Address::from_str(a).map_err(eyre::Report::from))
+                .collect()
+        };
+        Ok(Self {
+            stablecoins: parse(&cfg.stablecoins)?,
+            reference_pairs: parse(&cfg.reference_pairs)?,
+            anchored: HashMap::new(),
+        })
+    }
+
+    /// Re-anchors one side of a reference pair from the other side. A side
+    /// is never priced from an anchor this same pair produced.
+    pub fn update(&mut self, pair: &Pair, tick: &PriceTick) {
+        if !self.reference_pairs.contains(&pair.address) {
+            return;
+        }
+        let (token0, token1) = (pair.token0.address, pair.token1.address);
+        let quote0 = self
+            .anchor(token0)
+            .filter(|p| p.source != Some(pair.address));
+        let quote1 = self
+            .anchor(token1)
+            .filter(|p| p.source != Some(pair.address));
+
+        let (token, usd, quote) = match (quote0, quote1) {
+            (_, Some(q1)) if !self.stablecoins.contains(&token0) => {
+                (token0, tick.token0_token1 * q1.usd, q1)
+            }
+            (Some(q0), _) if !self.stablecoins.contains(&token1) => {
+                (token1, tick.token1_token0 * q0.usd, q0)
+            }
+            _ => return,
+        };
+        if !usd.is_finite() || usd <= 0.0 {
+            return;
+        }
+
+        let route = extend_route(pair, &quote);
+        debug!("anchor {token} at ${usd} via {route}");
+        self.anchored.insert(
+            token,
+            UsdPrice {
+                usd,
+                route,
+                source: Some(pair.address),
+            },
+        );
+    }
+
+    /// Fills the USD fields of `tick`. A token without an anchor of its own is
+    /// priced through this pair when the other side has one.
+    pub fn apply(&self, pair: &Pair, tick: &mut PriceTick) {
+        let anchor0 = self.anchor(pair.token0.address);
+        let anchor1 = self.anchor(pair.token1.address);
+
+        let (usd0, usd1, route) = match (anchor0, anchor1) {
+            (Some(a0), Some(a1)) => {
+                let route = if a0.route.len() <= a1.route.len() {
+                    a0.route
+                } else {
+                    a1.route
+                };
+                (a0.usd, a1.usd, route)
+            }
+            (None, Some(a1)) => (tick.token0_token1 * a1.usd, a1.usd, extend_route(pair, &a1)),
+            (Some(a0), None) => (a0.usd, tick.token1_token0 * a0.usd, extend_route(pair, &a0)),
+            (None, None) => return,
+        };
+
+        let reserve0 = to_units(U256::from(tick.token0_reserve), pair.token0.decimals);
+        let reserve1 = to_units(U256::from(tick.token1_reserve), pair.token1.decimals);
+
+        tick.token0_usd = Some(usd0);
+        tick.token1_usd = Some(usd1);
+        tick.liquidity_usd = Some(reserve0 * usd0 + reserve1 * usd1);
+        tick.usd_route = Some(route);
+    }
+
+    fn anchor(&self, token: Address) -> Option<UsdPrice> {
+        if self.stablecoins.contains(&token) {
+            return Some(UsdPrice {
+                usd: 1.0,
+                route: STABLE_ROUTE.to_string(),
+                source: None,
+            });
+        }
+        self.anchored.get(&token).cloned()
+    }
+}
+
+/// `PEPE/WETH` priced through an anchor routed `WETH/USDC` gives
+/// `PEPE/WETH>WETH/USDC`.
+fn extend_route(pair: &Pair, quote: &UsdPrice) -> String {
+    let label = format!("{}/{}", pair.token0.symbol, pair.token1.symbol);
+    if quote.route == STABLE_ROUTE {
+        label
+    } else {
+        format!("{label}>{}", quote.route)
+    }
+}
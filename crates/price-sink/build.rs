@@ -0,0 +1,4 @@
+// `sqlx::migrate!()` embeds `migrations/`, which cargo does not watch on its own.
+fn main() {
+    println!("cargo:rerun-if-changed=migrations");
+}
@@ -0,0 +1,35 @@
+-- Tables created by hand from the README DDL are adopted as they are.
+CREATE TABLE IF NOT EXISTS price_ticks (
+  time TIMESTAMPTZ NOT NULL,
+
+  pair_address TEXT NOT NULL,
+  token0_address TEXT NOT NULL,
+  token0_symbol TEXT NOT NULL,
+  token0_reserve NUMERIC NOT NULL,
+  token1_address TEXT NOT NULL,
+  token1_symbol TEXT NOT NULL,
+  token1_reserve NUMERIC NOT NULL,
+
+  token0_token1 DOUBLE PRECISION NOT NULL,
+  token1_token0 DOUBLE PRECISION NOT NULL,
+
+  block_number BIGINT NOT NULL,
+  transaction_hash TEXT NOT NULL
+);
+
+ALTER TABLE price_ticks
+  ADD COLUMN IF NOT EXISTS token0_token1_exact NUMERIC,
+  ADD COLUMN IF NOT EXISTS token1_token0_exact NUMERIC,
+  ADD COLUMN IF NOT EXISTS base_symbol TEXT,
+  ADD COLUMN IF NOT EXISTS quote_symbol TEXT,
+  ADD COLUMN IF NOT EXISTS price DOUBLE PRECISION,
+  ADD COLUMN IF NOT EXISTS price_exact NUMERIC,
+  ADD COLUMN IF NOT EXISTS token0_usd DOUBLE PRECISION,
+  ADD COLUMN IF NOT EXISTS token1_usd DOUBLE PRECISION,
+  ADD COLUMN IF NOT EXISTS liquidity_usd DOUBLE PRECISION,
+  ADD COLUMN IF NOT EXISTS usd_route TEXT;
+
+SELECT create_hypertable('price_ticks', by_range('time'), if_not_exists => TRUE);
+
+CREATE INDEX IF NOT EXISTS price_ticks_pair_time_idx
+  ON price_ticks (pair_address, time DESC);
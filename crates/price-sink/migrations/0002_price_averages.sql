@@ -0,0 +1,19 @@
+CREATE TABLE IF NOT EXISTS price_averages (
+  time TIMESTAMPTZ NOT NULL,
+  pair_address TEXT NOT NULL,
+  token0_symbol TEXT NOT NULL,
+  token1_symbol TEXT NOT NULL,
+  window_label TEXT NOT NULL,
+  elapsed_secs BIGINT NOT NULL,
+  token0_twap DOUBLE PRECISION NOT NULL,
+  token1_twap DOUBLE PRECISION NOT NULL,
+  token0_vwap DOUBLE PRECISION,
+  token0_volume DOUBLE PRECISION NOT NULL,
+  token1_volume DOUBLE PRECISION NOT NULL,
+  block_number BIGINT NOT NULL
+);
+
+SELECT create_hypertable('price_averages', by_range('time'), if_not_exists => TRUE);
+
+CREATE INDEX IF NOT EXISTS price_averages_pair_time_idx
+  ON price_averages (pair_address, window_label, time DESC);
@@ -0,0 +1,21 @@
+-- A tick is identified by (chain_id, transaction_hash, log_index, pair_address).
+-- time joins the key because unique indexes on a hypertable must include its
+-- partitioning column. Ticks from before log_index get -1, so replays of them
+-- conflict too instead of slipping past as NULLs.
+ALTER TABLE price_ticks
+  ADD COLUMN IF NOT EXISTS chain_id BIGINT NOT NULL DEFAULT 1,
+  ADD COLUMN IF NOT EXISTS log_index BIGINT NOT NULL DEFAULT -1;
+
+-- Keep one row of each key written twice before the key existed.
+DELETE FROM price_ticks a
+  USING price_ticks b
+  WHERE a.tableoid = b.tableoid
+    AND a.ctid < b.ctid
+    AND a.chain_id = b.chain_id
+    AND a.transaction_hash = b.transaction_hash
+    AND a.log_index = b.log_index
+    AND a.pair_address = b.pair_address
+    AND a.time = b.time;
+
+CREATE UNIQUE INDEX IF NOT EXISTS price_ticks_natural_key_idx
+  ON price_ticks (chain_id, transaction_hash, log_index, pair_address, time);
@@ -0,0 +1,29 @@
+-- Pair and token metadata mirrored from pair-enricher's KV bucket; join with
+-- price_ticks on pair_address and token0_address/token1_address.
+CREATE TABLE IF NOT EXISTS tokens (
+  chain_id BIGINT NOT NULL,
+  address TEXT NOT NULL,
+  symbol TEXT NOT NULL,
+  decimals SMALLINT NOT NULL,
+  total_supply NUMERIC NOT NULL,
+  verified BOOLEAN NOT NULL,
+  risk_flags TEXT[],
+  updated_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+  PRIMARY KEY (chain_id, address)
+);
+
+CREATE TABLE IF NOT EXISTS pairs (
+  chain_id BIGINT NOT NULL,
+  address TEXT NOT NULL,
+  token0_address TEXT NOT NULL,
+  token1_address TEXT NOT NULL,
+  factory TEXT,
+  created_block BIGINT,
+  updated_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+  PRIMARY KEY (chain_id, address),
+  FOREIGN KEY (chain_id, token0_address) REFERENCES tokens (chain_id, address),
+  FOREIGN KEY (chain_id, token1_address) REFERENCES tokens (chain_id, address)
+);
+
+CREATE INDEX IF NOT EXISTS pairs_token0_idx ON pairs (chain_id, token0_address);
+CREATE INDEX IF NOT EXISTS pairs_token1_idx ON pairs (chain_id, token1_address);
@@ -0,0 +1,17 @@
+-- An average is identified by (pair_address, window_label, block_number), with
+-- time in the key as the hypertable's partitioning column. A second Sync of a
+-- pair in the same block keeps the first average, so redeliveries and replays
+-- write nothing twice.
+
+-- Keep one row of each key written twice before the key existed.
+DELETE FROM price_averages a
+  USING price_averages b
+  WHERE a.tableoid = b.tableoid
+    AND a.ctid < b.ctid
+    AND a.pair_address = b.pair_address
+    AND a.window_label = b.window_label
+    AND a.block_number = b.block_number
+    AND a.time = b.time;
+
+CREATE UNIQUE INDEX IF NOT EXISTS price_averages_natural_key_idx
+  ON price_averages (pair_address, window_label, block_number, time);
@@ -1,4 +1,4 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use config::{Config, File};
 use eyre::Result;
 use serde::Deserialize;
@@ -10,6 +10,12 @@ use tracing_subscriber::{fmt, prelude::*, EnvFilter};
 pub struct AppConfig {
     pub nats: NatsConfig,
     pub timescale: TimescaleConfig,
+    pub filter: FilterConfig,
+    pub batch: BatchConfig,
+    #[serde(default)]
+    pub storage: StorageConfig,
+    pub twap: Option<TwapConfig>,
+    pub pairs: Option<PairsConfig>,
     pub log: Option<LogConfig>,
 }
 
@@ -23,6 +29,77 @@ pub struct NatsConfig {
 #[derive(Debug, Deserialize)]
 pub struct TimescaleConfig {
     pub dsn: String,
+    /// Stored with every tick as part of its natural key.
+    pub chain_id: u64,
+    /// Apply pending migrations on start instead of only checking the schema.
+    pub migrate_on_start: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FilterConfig {
+    /// Only store ticks whose tokens are both on a curated token list.
+    pub verified_only: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BatchConfig {
+    /// Ticks written per transaction. Keep below the consumer's
+    /// `max_ack_pending` (1000 by default).
+    pub max_size: usize,
+    /// Longest a batch waits for more ticks after its first one.
+    pub max_wait_ms: u64,
+}
+
+/// Database-side storage management, applied on every start. Intervals are
+/// Postgres interval literals such as `7 days`.
+#[derive(Debug, Deserialize, Default)]
+pub struct StorageConfig {
+    /// Policies of the `price_ticks` hypertable.
+    pub ticks: Option<StoragePolicy>,
+    /// Policies of the `price_averages` hypertable.
+    pub averages: Option<StoragePolicy>,
+    /// OHLC continuous aggregates over `price_ticks`.
+    #[serde(default)]
+    pub aggregates: Vec<AggregateConfig>,
+}
+
+/// A missing setting removes the corresponding policy; chunks that were
+/// already compressed stay compressed.
+#[derive(Debug, Deserialize, Default)]
+pub struct StoragePolicy {
+    /// Compress chunks older than this.
+    pub compress_after: Option<String>,
+    /// Drop chunks older than this.
+    pub drop_after: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AggregateConfig {
+    /// View name, e.g. `price_candles_1m`.
+    pub name: String,
+    /// Candle width, e.g. `1 minute`. Fixed once the view exists.
+    pub bucket: String,
+    /// The refresh policy materializes `[now - refresh_start, now - refresh_end]`
+    /// every `refresh_every`.
+    pub refresh_start: String,
+    pub refresh_end: String,
+    pub refresh_every: String,
+    #[serde(flatten)]
+    pub policy: StoragePolicy,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TwapConfig {
+    /// Subject of the `AveragePrice` records of `twap-oracle`, stored in the
+    /// `price_averages` table.
+    pub subject_name: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PairsConfig {
+    /// KV bucket of `pair-enricher`, mirrored into the `pairs` and `tokens`
+    /// tables.
+    pub kv_bucket: String,
 }
 
 #[derive(Debug, Deserialize)]
@@ -33,6 +110,8 @@ pub struct LogConfig {
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Commands>,
     #[arg(long)]
     server_url: Option<String>,
     #[arg(long)]
@@ -41,25 +120,51 @@ struct Cli {
     stream_name: Option<String>,
     #[arg(long)]
     dsn: Option<String>,
+    #[arg(long)]
+    chain_id: Option<u64>,
+    #[arg(long)]
+    migrate_on_start: Option<bool>,
+    #[arg(long)]
+    verified_only: Option<bool>,
+    #[arg(long)]
+    batch_max_size: Option<u64>,
+    #[arg(long)]
+    batch_max_wait_ms: Option<u64>,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum Commands {
+    /// Apply pending schema migrations and exit.
+    Migrate,
 }
 
 impl AppConfig {
-    pub fn from_file_or_cli() -> Result<Self> {
+    pub fn from_file_or_cli() -> Result<(AppConfig, Option<Commands>)> {
         let cli = Cli::parse();
         let config_path1 = Path::new("config/price-sink.toml");
         let config_path2 = Path::new("price-sink.toml");
 
         let cfg: AppConfig = Config::builder()
+            .set_default("timescale.chain_id", 1)?
+            .set_default("timescale.migrate_on_start", true)?
+            .set_default("filter.verified_only", false)?
+            .set_default("batch.max_size", 500)?
+            .set_default("batch.max_wait_ms", 200)?
             .add_source(File::from(config_path1).required(false))
             .add_source(File::from(config_path2).required(false))
             .set_override_option("nats.server_url", cli.server_url)?
             .set_override_option("nats.subject_name", cli.subject_name)?
             .set_override_option("nats.stream_name", cli.stream_name)?
             .set_override_option("timescale.dsn", cli.dsn)?
+            .set_override_option("timescale.chain_id", cli.chain_id)?
+            .set_override_option("timescale.migrate_on_start", cli.migrate_on_start)?
+            .set_override_option("filter.verified_only", cli.verified_only)?
+            .set_override_option("batch.max_size", cli.batch_max_size)?
+            .set_override_option("batch.max_wait_ms", cli.batch_max_wait_ms)?
             .build()
             .map_err(eyre::Report::from)?
             .try_deserialize()?;
-        Ok(cfg)
+        Ok((cfg, cli.command))
     }
 
     pub fn init_log(&self) -> Result<()> {
@@ -1,22 +1,47 @@
+use async_nats::jetstream::{kv::Operation, AckKind, Message};
 use eyre::{eyre, Result};
 use futures_util::StreamExt;
-use tracing::{info, warn};
+use std::time::Duration;
+use tokio::time::Instant;
+use tracing::{debug, info, warn};
 
-use chain_model::PriceTick;
+use chain_model::{AveragePrice, Pair, PriceTick};
 
 mod init;
 mod mq;
 mod tsdb;
 
+/// Redelivery delay after a failed batch write, doubled per consecutive failure.
+const NAK_DELAY_MIN: Duration = Duration::from_secs(1);
+const NAK_DELAY_MAX: Duration = Duration::from_secs(60);
+
+fn nak_delay(failures: u32) -> Duration {
+    NAK_DELAY_MIN
+        .saturating_mul(1 << failures.min(10))
+        .min(NAK_DELAY_MAX)
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
-    let app_cfg = init::AppConfig::from_file_or_cli()?;
+    let (app_cfg, app_cmd) = init::AppConfig::from_file_or_cli()?;
     app_cfg.init_log()?;
     info!("starting price-sink with config: {app_cfg:#?}");
 
-    let tsdb = tsdb::TsdbClient::new(&app_cfg.timescale.dsn).await?;
+    let tsdb = tsdb::TsdbClient::new(&app_cfg.timescale.dsn, app_cfg.timescale.chain_id).await?;
     info!("connected to TimescaleDB");
 
+    if matches!(app_cmd, Some(init::Commands::Migrate)) {
+        tsdb.migrate().await?;
+        tsdb.apply_storage(&app_cfg.storage).await?;
+        info!("schema migrated");
+        return Ok(());
+    }
+    if app_cfg.timescale.migrate_on_start {
+        tsdb.migrate().await?;
+    }
+    tsdb.check_schema().await?;
+    tsdb.apply_storage(&app_cfg.storage).await?;
+
     let mq_client = mq::MqClient::new(
         &app_cfg.nats.server_url,
         &app_cfg.nats.subject_name,
@@ -24,18 +49,75 @@ async fn main() -> Result<()> {
     )
     .await?;
 
+    tokio::try_join!(
+        sink_ticks(&app_cfg, &mq_client, &tsdb),
+        sink_averages(&app_cfg, &mq_client, &tsdb),
+        sink_pairs(&app_cfg, &mq_client, &tsdb)
+    )?;
+
+    Ok(())
+}
+
+async fn sink_ticks(
+    app_cfg: &init::AppConfig,
+    mq_client: &mq::MqClient,
+    tsdb: &tsdb::TsdbClient,
+) -> Result<()> {
+    let max_size = app_cfg.batch.max_size.max(1);
+    let max_wait = Duration::from_millis(app_cfg.batch.max_wait_ms);
+
     let mut sub = mq_client.jetstream_pull_from(true).await?;
-    while let Some(msg_result) = sub.next().await {
+    let mut batch = TickBatch::new(max_size);
+    let mut deadline = Instant::now();
+    loop {
+        // Wait without limit for the first tick, then at most until the
+        // batch's deadline for the rest.
+        let next = if batch.rows.is_empty() {
+            sub.next().await
+        } else {
+            match tokio::time::timeout_at(deadline, sub.next()).await {
+                Ok(next) => next,
+                Err(_) => {
+                    batch.flush(tsdb).await?;
+                    continue;
+                }
+            }
+        };
+        let Some(msg_result) = next else {
+            break;
+        };
+
         let msg = msg_result?;
         match serde_json::from_slice::<PriceTick>(&msg.payload) {
+            Ok(tick)
+                if app_cfg.filter.verified_only
+                    && !(tick.token0_verified && tick.token1_verified) =>
+            {
+                debug!("skip unverified tick {}", tick.transaction_hash);
+                msg.ack()
+                    .await
+                    .map_err(|e| eyre!("ack message failed: {e}"))?;
+            }
             Ok(tick) => {
-                if let Err(e) = tsdb.write(&tick).await {
-                    warn!("failed to write tick: {e}");
-                } else {
-                    info!("wrote tick {}", tick.transaction_hash);
-                    msg.ack()
-                        .await
-                        .map_err(|e| eyre!("ack message failed: {e}"))?;
+                let transaction_hash = tick.transaction_hash.clone();
+                match tsdb::TickRow::new(tick) {
+                    Ok(row) => {
+                        if batch.rows.is_empty() {
+                            deadline = Instant::now() + max_wait;
+                        }
+                        batch.msgs.push(msg);
+                        batch.rows.push(row);
+                        if batch.rows.len() >= max_size {
+                            batch.flush(tsdb).await?;
+                        }
+                    }
+                    Err(e) => {
+                        // Redelivery cannot fix it, so stop it from coming back.
+                        warn!("drop unstorable tick {transaction_hash}: {e}");
+                        msg.ack_with(AckKind::Term)
+                            .await
+                            .map_err(|e| eyre!("term message failed: {e}"))?;
+                    }
                 }
             }
             Err(e) => {
@@ -46,6 +128,144 @@ async fn main() -> Result<()> {
             }
         }
     }
+    batch.flush(tsdb).await?;
+
+    Ok(())
+}
+
+/// Ticks waiting to be written, with the messages they came from.
+struct TickBatch {
+    msgs: Vec<Message>,
+    rows: Vec<tsdb::TickRow>,
+    /// Consecutive failed writes, which stretch the redelivery delay.
+    failures: u32,
+}
+
+impl TickBatch {
+    fn new(max_size: usize) -> Self {
+        Self {
+            msgs: Vec::with_capacity(max_size),
+            rows: Vec::with_capacity(max_size),
+            failures: 0,
+        }
+    }
+
+    /// Writes the batch in one transaction, then acks every message of it
+    /// after the commit. On failure every message is naked with a delay that
+    /// doubles per consecutive failure, so an outage is not retried in a loop.
+    async fn flush(&mut self, tsdb: &tsdb::TsdbClient) -> Result<()> {
+        if self.rows.is_empty() {
+            return Ok(());
+        }
+        let written = tsdb.write_batch(&self.rows).await;
+        self.rows.clear();
+        match written {
+            Ok(inserted) => {
+                self.failures = 0;
+                info!("wrote {inserted} of {} ticks", self.msgs.len());
+                for msg in self.msgs.drain(..) {
+                    msg.ack()
+                        .await
+                        .map_err(|e| eyre!("ack message failed: {e}"))?;
+                }
+            }
+            Err(e) => {
+                let delay = nak_delay(self.failures);
+                self.failures += 1;
+                warn!(
+                    "failed to write {} ticks, redelivering in {delay:?}: {e}",
+                    self.msgs.len()
+                );
+                for msg in self.msgs.drain(..) {
+                    msg.ack_with(AckKind::Nak(Some(delay)))
+                        .await
+                        .map_err(|e| eyre!("nak message failed: {e}"))?;
+                }
+                tokio::time::sleep(delay).await;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Stores `twap-oracle` averages when a `[twap]` subject is configured.
+async fn sink_averages(
+    app_cfg: &init::AppConfig,
+    mq_client: &mq::MqClient,
+    tsdb: &tsdb::TsdbClient,
+) -> Result<()> {
+    let Some(twap) = &app_cfg.twap else {
+        return Ok(());
+    };
+
+    let mut sub = mq_client
+        .jetstream_pull("price-sink-twap", &twap.subject_name, true)
+        .await?;
+    // Consecutive failed writes, backed off like the tick batches.
+    let mut failures = 0;
+    while let Some(msg_result) = sub.next().await {
+        let msg = msg_result?;
+        match serde_json::from_slice::<AveragePrice>(&msg.payload) {
+            Ok(average) => {
+                if let Err(e) = tsdb.write_average(&average).await {
+                    let delay = nak_delay(failures);
+                    failures += 1;
+                    warn!("failed to write average, redelivering in {delay:?}: {e}");
+                    msg.ack_with(AckKind::Nak(Some(delay)))
+                        .await
+                        .map_err(|e| eyre!("nak message failed: {e}"))?;
+                    tokio::time::sleep(delay).await;
+                    continue;
+                }
+                failures = 0;
+                debug!(
+                    "wrote {} average of {}",
+                    average.window, average.pair_address
+                );
+            }
+            Err(e) => warn!("invalid payload: {e}"),
+        }
+        msg.ack()
+            .await
+            .map_err(|e| eyre!("ack message failed: {e}"))?;
+    }
+    Ok(())
+}
 
+/// Mirrors `pair-enricher`'s KV bucket into the `pairs` and `tokens` tables
+/// when a `[pairs]` bucket is configured.
+async fn sink_pairs(
+    app_cfg: &init::AppConfig,
+    mq_client: &mq::MqClient,
+    tsdb: &tsdb::TsdbClient,
+) -> Result<()> {
+    let Some(pairs) = &app_cfg.pairs else {
+        return Ok(());
+    };
+
+    let kv = mq_client.kv_store(&pairs.kv_bucket).await?;
+    // Every start replays the latest value of each key, which the upserts
+    // absorb.
+    let mut watch = kv
+        .watch_with_history(">")
+        .await
+        .map_err(|e| eyre!("KV watch failed: {e}"))?;
+    while let Some(entry) = watch.next().await {
+        let entry = entry.map_err(|e| eyre!("KV watch error: {e}"))?;
+        match entry.operation {
+            Operation::Put => match serde_json::from_slice::<Pair>(&entry.value) {
+                Ok(pair) => {
+                    tsdb.upsert_pair(&pair).await?;
+                    debug!("upserted pair {}", pair.address);
+                }
+                Err(e) => warn!("invalid pair '{}': {e}", entry.key),
+            },
+            Operation::Delete | Operation::Purge => {
+                tsdb.delete_pair(&entry.key).await?;
+                debug!("deleted pair {}", entry.key);
+            }
+        }
+    }
+    warn!("KV watch of {} ended", pairs.kv_bucket);
     Ok(())
 }
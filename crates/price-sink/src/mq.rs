@@ -1,5 +1,5 @@
 use async_nats::{
-    jetstream::{self, consumer::DeliverPolicy},
+    jetstream::{self, consumer::DeliverPolicy, kv::Store},
     Client,
 };
 use eyre::{Result, WrapErr};
@@ -26,6 +26,16 @@ impl MqClient {
     pub async fn jetstream_pull_from(
         &self,
         from_start: bool,
+    ) -> Result<impl StreamExt<Item = Result<async_nats::jetstream::Message>>> {
+        self.jetstream_pull("price-sink", &self.subject_name, from_start)
+            .await
+    }
+
+    pub async fn jetstream_pull(
+        &self,
+        durable_name: &str,
+        subject_name: &str,
+        from_start: bool,
     ) -> Result<impl StreamExt<Item = Result<async_nats::jetstream::Message>>> {
         let js = jetstream::new(self.nats.clone());
 
@@ -36,13 +46,13 @@ impl MqClient {
 
         let consumer = stream
             .create_consumer(async_nats::jetstream::consumer::pull::Config {
-                durable_name: Some("price-sink".to_string()),
+                durable_name: Some(durable_name.to_string()),
                 deliver_policy: if from_start {
                     DeliverPolicy::All
                 } else {
                     DeliverPolicy::New
                 },
-                filter_subject: self.subject_name.clone(),
+                filter_subject: subject_name.to_string(),
                 ..Default::default()
             })
             .await
@@ -56,4 +66,11 @@ impl MqClient {
             msg_result.map_err(|e| eyre::eyre!("JetStream message error: {}", e))
         }))
     }
+
+    pub async fn kv_store(&self, bucket: &str) -> Result<Store> {
+        let js = jetstream::new(self.nats.clone());
+        js.get_key_value(bucket)
+            .await
+            .map_err(|e| eyre::eyre!("KV bucket error: {e}"))
+    }
 }
@@ -1,60 +1,515 @@
 use bigdecimal::BigDecimal;
-use chain_model::PriceTick;
+use chain_model::{AveragePrice, Pair, PriceTick};
 use chrono::TimeZone;
 use eyre::{eyre, Result};
-use std::str::FromStr;
-use sqlx::{PgPool, postgres::PgPoolOptions};
+use sqlx::{migrate::Migrator, postgres::PgPoolOptions, PgPool, Postgres, QueryBuilder};
+use std::{collections::HashMap, str::FromStr};
+use tracing::info;
 
+use crate::init::{AggregateConfig, StorageConfig, StoragePolicy};
+
+/// Versioned schema of the tables price-sink writes, in `migrations/`.
+static MIGRATOR: Migrator = sqlx::migrate!();
+
+/// 24 binds per row keeps a statement well below Postgres' 65535 parameters.
+const ROWS_PER_INSERT: usize = 1000;
+
+/// A tick with the column values that need converting, checked before it
+/// joins a batch so one bad tick cannot fail the others.
+pub struct TickRow {
+    tick: PriceTick,
+    time: chrono::DateTime<chrono::Utc>,
+    token0_reserve: BigDecimal,
+    token1_reserve: BigDecimal,
+    token0_token1_exact: Option<BigDecimal>,
+    token1_token0_exact: Option<BigDecimal>,
+    price_exact: Option<BigDecimal>,
+    oriented: bool,
+    block_number: i64,
+    log_index: i64,
+}
+
+impl TickRow {
+    pub fn new(tick: PriceTick) -> Result<Self> {
+        Ok(Self {
+            time: chrono::Utc
+                .timestamp_opt(tick.block_timestamp as i64, 0)
+                .single()
+                .ok_or_else(|| eyre!("invalid timestamp"))?,
+            token0_reserve: BigDecimal::from_str(&tick.token0_reserve.to_string())?,
+            token1_reserve: BigDecimal::from_str(&tick.token1_reserve.to_string())?,
+            // Ticks from before exact prices were added carry empty strings.
+            token0_token1_exact: exact_decimal(&tick.token0_token1_exact)?,
+            token1_token0_exact: exact_decimal(&tick.token1_token0_exact)?,
+            price_exact: exact_decimal(&tick.price_exact)?,
+            // Ticks from before base/quote orientation carry no base token.
+            oriented: !tick.base_address.is_empty(),
+            block_number: i64::try_from(tick.block_number)?,
+            // -1 keys ticks from producers without log indexes, see migration 0003.
+            log_index: tick.log_index.map(i64::try_from).transpose()?.unwrap_or(-1),
+            tick,
+        })
+    }
+}
+
+/// A relation that compression and retention policies apply to.
+enum Relation<'a> {
+    Hypertable {
+        name: &'a str,
+        segment_by: &'a str,
+        order_by: &'a str,
+    },
+    Aggregate {
+        name: &'a str,
+    },
+}
+
+impl Relation<'_> {
+    fn name(&self) -> &str {
+        match self {
+            Relation::Hypertable { name, .. } | Relation::Aggregate { name } => name,
+        }
+    }
+}
 
 #[derive(Clone, Debug)]
 pub struct TsdbClient {
     pool: PgPool,
+    chain_id: i64,
 }
 
 impl TsdbClient {
-    pub async fn new(dsn: &str) -> Result<Self> {
+    pub async fn new(dsn: &str, chain_id: u64) -> Result<Self> {
         let pool = PgPoolOptions::new()
             .max_connections(16)
             .connect(dsn)
             .await?;
-        Ok(Self { pool })
+        Ok(Self {
+            pool,
+            chain_id: chain_id as i64,
+        })
+    }
+
+    /// Applies the migrations the database has not seen yet.
+    pub async fn migrate(&self) -> Result<()> {
+        MIGRATOR.run(&self.pool).await?;
+        Ok(())
+    }
+
+    /// Fails unless the database is at exactly the schema version this build
+    /// was compiled with, unchanged.
+    pub async fn check_schema(&self) -> Result<()> {
+        let applied: Vec<(i64, Vec<u8>, bool)> =
+            sqlx::query_as("SELECT version, checksum, success FROM _sqlx_migrations")
+                .fetch_all(&self.pool)
+                .await
+                .map_err(|e| eyre!("schema not initialized, run `price-sink migrate`: {e}"))?;
+
+        let mut applied: HashMap<i64, (Vec<u8>, bool)> = applied
+            .into_iter()
+            .map(|(version, checksum, success)| (version, (checksum, success)))
+            .collect();
+        for migration in MIGRATOR.iter() {
+            let Some((checksum, success)) = applied.remove(&migration.version) else {
+                return Err(eyre!(
+                    "migration {} ({}) not applied, run `price-sink migrate`",
+                    migration.version,
+                    migration.description
+                ));
+            };
+            if !success {
+                return Err(eyre!(
+                    "migration {} ({}) failed partway, fix the database by hand",
+                    migration.version,
+                    migration.description
+                ));
+            }
+            if checksum != *migration.checksum {
+                return Err(eyre!(
+                    "migration {} ({}) differs from the one applied",
+                    migration.version,
+                    migration.description
+                ));
+            }
+        }
+        if let Some(version) = applied.keys().max() {
+            return Err(eyre!(
+                "database has migration {version} unknown to this build, upgrade price-sink"
+            ));
+        }
+        Ok(())
+    }
+
+    /// Writes all `ticks` in one transaction of multi-row inserts and returns
+    /// how many were new. Ticks already stored under their natural key are
+    /// skipped, so redelivered and replayed messages are harmless.
+    pub async fn write_batch(&self, rows: &[TickRow]) -> Result<u64> {
+        let mut inserted = 0;
+        let mut tx = self.pool.begin().await?;
+        for chunk in rows.chunks(ROWS_PER_INSERT) {
+            let mut query = QueryBuilder::<Postgres>::new(
+                r#"
+                INSERT INTO price_ticks (
+                    chain_id, time, pair_address,
+                    token0_address, token0_symbol, token0_reserve,
+                    token1_address, token1_symbol, token1_reserve,
+                    token0_token1, token1_token0,
+                    token0_token1_exact, token1_token0_exact,
+                    base_symbol, quote_symbol, price, price_exact,
+                    token0_usd, token1_usd, liquidity_usd, usd_route,
+                    block_number, transaction_hash, log_index
+                )
+                "#,
+            );
+            query.push_values(chunk, |mut b, row| {
+                let tick = &row.tick;
+                b.push_bind(self.chain_id)
+                    .push_bind(row.time)
+                    .push_bind(tick.pair_address.as_str())
+                    .push_bind(tick.token0_address.as_str())
+                    .push_bind(tick.token0_symbol.as_str())
+                    .push_bind(row.token0_reserve.clone())
+                    .push_bind(tick.token1_address.as_str())
+                    .push_bind(tick.token1_symbol.as_str())
+                    .push_bind(row.token1_reserve.clone())
+                    .push_bind(tick.token0_token1)
+                    .push_bind(tick.token1_token0)
+                    .push_bind(row.token0_token1_exact.clone())
+                    .push_bind(row.token1_token0_exact.clone())
+                    .push_bind(row.oriented.then_some(tick.base_symbol.as_str()))
+                    .push_bind(row.oriented.then_some(tick.quote_symbol.as_str()))
+                    .push_bind(row.oriented.then_some(tick.price))
+                    .push_bind(row.price_exact.clone())
+                    .push_bind(tick.token0_usd)
+                    .push_bind(tick.token1_usd)
+                    .push_bind(tick.liquidity_usd)
+                    .push_bind(tick.usd_route.as_deref())
+                    .push_bind(row.block_number)
+                    .push_bind(tick.transaction_hash.as_str())
+                    .push_bind(row.log_index);
+            });
+            query.push(
+                " ON CONFLICT (chain_id, transaction_hash, log_index, pair_address, time) DO NOTHING",
+            );
+            inserted += query.build().execute(&mut *tx).await?.rows_affected();
+        }
+        tx.commit().await?;
+        Ok(inserted)
     }
 
-    pub async fn write(&self, tick: &PriceTick) -> Result<()> {
+    pub async fn write_average(&self, average: &AveragePrice) -> Result<()> {
         let date_time = chrono::Utc
-            .timestamp_opt(tick.block_timestamp as i64, 0)
+            .timestamp_opt(average.block_timestamp as i64, 0)
             .single()
             .ok_or_else(|| eyre!("invalid timestamp"))?;
 
-        let token0_reserve_dec = BigDecimal::from_str(&tick.token0_reserve.to_string())?;
-        let token1_reserve_dec = BigDecimal::from_str(&tick.token1_reserve.to_string())?;
-
         sqlx::query(
             r#"
-            INSERT INTO price_ticks (
-                time, pair_address,
-                token0_address, token0_symbol, token0_reserve,
-                token1_address, token1_symbol, token1_reserve,
-                token0_token1, token1_token0,
-                block_number, transaction_hash
+            INSERT INTO price_averages (
+                time, pair_address, token0_symbol, token1_symbol,
+                window_label, elapsed_secs,
+                token0_twap, token1_twap, token0_vwap,
+                token0_volume, token1_volume, block_number
             )
             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
+            ON CONFLICT (pair_address, window_label, block_number, time) DO NOTHING
             "#,
         )
         .bind(date_time)
-        .bind(tick.pair_address.to_string())
-        .bind(tick.token0_address.to_string())
-        .bind(tick.token0_symbol.as_str())
-        .bind(token0_reserve_dec)
-        .bind(tick.token1_address.to_string())
-        .bind(tick.token1_symbol.as_str())
-        .bind(token1_reserve_dec)
-        .bind(tick.token0_token1)
-        .bind(tick.token1_token0)
-        .bind(tick.block_number as i64)
-        .bind(tick.transaction_hash.to_string())
+        .bind(average.pair_address.as_str())
+        .bind(average.token0_symbol.as_str())
+        .bind(average.token1_symbol.as_str())
+        .bind(average.window.as_str())
+        .bind(average.elapsed as i64)
+        .bind(average.token0_twap)
+        .bind(average.token1_twap)
+        .bind(average.token0_vwap)
+        .bind(average.token0_volume)
+        .bind(average.token1_volume)
+        .bind(average.block_number as i64)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Upserts `pair` and both of its tokens in one transaction.
+    pub async fn upsert_pair(&self, pair: &Pair) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        for token in [&pair.token0, &pair.token1] {
+            let risk_flags: Option<Vec<&str>> = token
+                .risk
+                .as_ref()
+                .map(|risk| risk.flags.iter().map(|flag| flag.as_str()).collect());
+            sqlx::query(
+                r#"
+                INSERT INTO tokens (
+                    chain_id, address, symbol, decimals, total_supply, verified, risk_flags
+                )
+                VALUES ($1, $2, $3, $4, $5, $6, $7)
+                ON CONFLICT (chain_id, address) DO UPDATE SET
+                    symbol = EXCLUDED.symbol,
+                    decimals = EXCLUDED.decimals,
+                    total_supply = EXCLUDED.total_supply,
+                    verified = EXCLUDED.verified,
+                    risk_flags = EXCLUDED.risk_flags,
+                    updated_at = now()
+                "#,
+            )
+            .bind(self.chain_id)
+            .bind(token.address.to_string())
+            .bind(token.symbol.as_str())
+            .bind(token.decimals as i16)
+            .bind(BigDecimal::from_str(&token.total_supply.to_string())?)
+            .bind(token.verified)
+            .bind(risk_flags)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        // A later write without creation details keeps the known ones.
+        sqlx::query(
+            r#"
+            INSERT INTO pairs (
+                chain_id, address, token0_address, token1_address, factory, created_block
+            )
+            VALUES ($1, $2, $3, $4, $5, $6)
+            ON CONFLICT (chain_id, address) DO UPDATE SET
+                token0_address = EXCLUDED.token0_address,
+                token1_address = EXCLUDED.token1_address,
+                factory = COALESCE(EXCLUDED.factory, pairs.factory),
+                created_block = COALESCE(EXCLUDED.created_block, pairs.created_block),
+                updated_at = now()
+            "#,
+        )
+        .bind(self.chain_id)
+        .bind(pair.address.to_string())
+        .bind(pair.token0.address.to_string())
+        .bind(pair.token1.address.to_string())
+        .bind(pair.factory.map(|factory| factory.to_string()))
+        .bind(pair.created_block.map(|block| block as i64))
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Removes a pair deleted from the KV bucket; its tokens stay.
+    pub async fn delete_pair(&self, address: &str) -> Result<()> {
+        sqlx::query("DELETE FROM pairs WHERE chain_id = $1 AND address = $2")
+            .bind(self.chain_id)
+            .bind(address)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Creates the configured continuous aggregates and brings the refresh,
+    /// compression and retention policies in line with `storage`.
+    pub async fn apply_storage(&self, storage: &StorageConfig) -> Result<()> {
+        let ticks = Relation::Hypertable {
+            name: "price_ticks",
+            // Covers the natural key, as compression of a uniquely indexed
+            // hypertable requires.
+            segment_by: "chain_id, pair_address",
+            order_by: "time DESC, transaction_hash, log_index",
+        };
+        let averages = Relation::Hypertable {
+            name: "price_averages",
+            segment_by: "pair_address, window_label",
+            order_by: "time DESC",
+        };
+        let default = StoragePolicy::default();
+        self.apply_policy(&ticks, storage.ticks.as_ref().unwrap_or(&default))
+            .await?;
+        self.apply_policy(&averages, storage.averages.as_ref().unwrap_or(&default))
+            .await?;
+
+        for aggregate in &storage.aggregates {
+            self.apply_aggregate(aggregate).await?;
+        }
+        Ok(())
+    }
+
+    async fn apply_aggregate(&self, aggregate: &AggregateConfig) -> Result<()> {
+        let name = identifier(&aggregate.name)?;
+        let bucket = self.interval(&aggregate.bucket).await?;
+        let comment = format!("price-sink bucket {bucket}");
+
+        // The bucket is part of the view's definition, recorded in its comment.
+        let existing: Option<Option<String>> = sqlx::query_scalar(
+            "SELECT obj_description(c.oid, 'pg_class') FROM pg_class c WHERE c.relname = $1",
+        )
+        .bind(name)
+        .fetch_optional(&self.pool)
+        .await?;
+        match existing {
+            Some(found) if found.as_deref() == Some(comment.as_str()) => {}
+            Some(found) => {
+                return Err(eyre!(
+                    "aggregate {name} exists as '{}', drop it to change its bucket to {bucket}",
+                    found.unwrap_or_default()
+                ));
+            }
+            None => {
+                sqlx::query(&format!(
+                    r#"
+                    CREATE MATERIALIZED VIEW {name}
+                    WITH (timescaledb.continuous) AS
+                    SELECT
+                        time_bucket(INTERVAL '{bucket}', time) AS bucket,
+                        chain_id,
+                        pair_address,
+                        base_symbol,
+                        quote_symbol,
+                        first(price, time) AS open,
+                        max(price) AS high,
+                        min(price) AS low,
+                        last(price, time) AS close,
+                        last(liquidity_usd, time) AS liquidity_usd,
+                        count(*) AS ticks
+                    FROM price_ticks
+                    WHERE price IS NOT NULL
+                    GROUP BY bucket, chain_id, pair_address, base_symbol, quote_symbol
+                    WITH NO DATA
+                    "#
+                ))
+                .execute(&self.pool)
+                .await?;
+                sqlx::query(&format!(
+                    "COMMENT ON MATERIALIZED VIEW {name} IS '{comment}'"
+                ))
+                .execute(&self.pool)
+                .await?;
+                // The refresh policy only covers its window, so materialize
+                // the ticks already stored once.
+                sqlx::query(&format!(
+                    "CALL refresh_continuous_aggregate('{name}', NULL, NULL)"
+                ))
+                .execute(&self.pool)
+                .await?;
+                info!("created continuous aggregate {name} of {bucket}");
+            }
+        }
+
+        sqlx::query("SELECT remove_continuous_aggregate_policy($1::regclass, if_exists => true)")
+            .bind(name)
+            .execute(&self.pool)
+            .await?;
+        sqlx::query(
+            r#"
+            SELECT add_continuous_aggregate_policy($1::regclass,
+                start_offset => $2::interval,
+                end_offset => $3::interval,
+                schedule_interval => $4::interval)
+            "#,
+        )
+        .bind(name)
+        .bind(&aggregate.refresh_start)
+        .bind(&aggregate.refresh_end)
+        .bind(&aggregate.refresh_every)
         .execute(&self.pool)
         .await?;
+
+        self.apply_policy(&Relation::Aggregate { name }, &aggregate.policy)
+            .await
+    }
+
+    async fn apply_policy(&self, relation: &Relation<'_>, policy: &StoragePolicy) -> Result<()> {
+        let name = relation.name();
+
+        sqlx::query("SELECT remove_compression_policy($1::regclass, if_exists => true)")
+            .bind(name)
+            .execute(&self.pool)
+            .await?;
+        if let Some(after) = &policy.compress_after {
+            self.enable_compression(relation).await?;
+            sqlx::query(
+                "SELECT add_compression_policy($1::regclass, compress_after => $2::interval)",
+            )
+            .bind(name)
+            .bind(after)
+            .execute(&self.pool)
+            .await?;
+        }
+
+        sqlx::query("SELECT remove_retention_policy($1::regclass, if_exists => true)")
+            .bind(name)
+            .execute(&self.pool)
+            .await?;
+        if let Some(after) = &policy.drop_after {
+            sqlx::query("SELECT add_retention_policy($1::regclass, drop_after => $2::interval)")
+                .bind(name)
+                .bind(after)
+                .execute(&self.pool)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Turns on compression unless it already is; its settings cannot change
+    /// while compressed chunks exist.
+    async fn enable_compression(&self, relation: &Relation<'_>) -> Result<()> {
+        let (lookup, alter) = match relation {
+            Relation::Hypertable {
+                name,
+                segment_by,
+                order_by,
+            } => (
+                "SELECT compression_enabled FROM timescaledb_information.hypertables \
+                 WHERE hypertable_name = $1",
+                format!(
+                    "ALTER TABLE {name} SET (timescaledb.compress, \
+                     timescaledb.compress_segmentby = '{segment_by}', \
+                     timescaledb.compress_orderby = '{order_by}')"
+                ),
+            ),
+            Relation::Aggregate { name } => (
+                "SELECT compression_enabled FROM timescaledb_information.continuous_aggregates \
+                 WHERE view_name = $1",
+                format!("ALTER MATERIALIZED VIEW {name} SET (timescaledb.compress = true)"),
+            ),
+        };
+        let enabled: Option<bool> = sqlx::query_scalar(lookup)
+            .bind(relation.name())
+            .fetch_optional(&self.pool)
+            .await?;
+        if enabled != Some(true) {
+            sqlx::query(&alter).execute(&self.pool).await?;
+            info!("enabled compression of {}", relation.name());
+        }
         Ok(())
     }
+
+    /// Checks `value` is an interval literal that can be inlined into DDL.
+    async fn interval<'a>(&self, value: &'a str) -> Result<&'a str> {
+        if value.contains('\'') {
+            return Err(eyre!("invalid interval '{value}'"));
+        }
+        sqlx::query("SELECT $1::interval")
+            .bind(value)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| eyre!("invalid interval '{value}': {e}"))?;
+        Ok(value)
+    }
+}
+
+/// Checks `name` is a plain lowercase SQL identifier.
+fn identifier(name: &str) -> Result<&str> {
+    let valid = name.starts_with(|c: char| c.is_ascii_lowercase() || c == '_')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+    if !valid {
+        return Err(eyre!("invalid aggregate name '{name}'"));
+    }
+    Ok(name)
+}
+
+fn exact_decimal(value: &str) -> Result<Option<BigDecimal>> {
+    if value.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(BigDecimal::from_str(value)?))
 }
@@ -0,0 +1,109 @@
+use alloy::primitives::Address;
+use async_nats::jetstream::kv::{Entry, Operation, Store};
+use eyre::{eyre, Result};
+use futures_util::StreamExt;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+use tracing::{debug, info, warn};
+
+use chain_model::Pair;
+
+#[derive(Debug)]
+struct CachedPair {
+    pair: Pair,
+    revision: u64,
+}
+
+/// Local copy of the pairs KV bucket, kept current by a background watch so
+/// quote requests never wait on NATS.
+#[derive(Clone, Default)]
+pub struct PairCache {
+    pairs: Arc<RwLock<HashMap<Address, CachedPair>>>,
+}
+
+impl PairCache {
+    /// Loads the current bucket contents, then keeps following updates.
+    pub async fn start(kv: &Store) -> Result<Self> {
+        let cache = Self::default();
+        let mut watch = kv
+            .watch_with_history(">")
+            .await
+            .map_err(|e| eyre!("KV watch failed: {e}"))?;
+
+        // The watch first replays the last value of every key; `delta` counts
+        // how many of those are still pending.
+        if kv.status().await?.values() > 0 {
+            while let Some(entry) = watch.next().await {
+                let entry = entry?;
+                let initial_done = entry.delta == 0;
+                cache.apply(entry);
+                if initial_done {
+                    break;
+                }
+            }
+        }
+        info!("loaded {} pairs from kv store", cache.len());
+
+        let updates = cache.clone();
+        tokio::spawn(async move {
+            while let Some(entry) = watch.next().await {
+                match entry {
+                    Ok(entry) => updates.apply(entry),
+                    Err(e) => warn!("KV watch error: {e}"),
+                }
+            }
+            warn!("KV watch ended, pair cache no longer updated");
+        });
+
+        Ok(cache)
+    }
+
+    pub fn get(&self, pair: &Address) -> Option<(Pair, u64)> {
+        let pairs = self.pairs.read().unwrap();
+        pairs
+            .get(pair)
+            .map(|cached| (cached.pair.clone(), cached.revision))
+    }
+
+    pub fn len(&self) -> usize {
+        self.pairs.read().unwrap().len()
+    }
+
+    fn apply(&self, entry: Entry) {
+        let address = match Address::from_str(&entry.key) {
+            Ok(address) => address,
+            Err(e) => {
+                warn!("Failed to parse key '{}' as address: {e}", entry.key);
+                return;
+            }
+        };
+
+        let mut pairs = self.pairs.write().unwrap();
+        if let Some(cached) = pairs.get(&address) {
+            if cached.revision >= entry.revision {
+                return;
+            }
+        }
+
+        match entry.operation {
+            Operation::Put => match serde_json::from_slice::<Pair>(&entry.value) {
+                Ok(pair) => {
+                    debug!("cache pair {} @ {}", entry.key, entry.revision);
+                    pairs.insert(
+                        address,
+                        CachedPair {
+                            pair,
+                            revision: entry.revision,
+                        },
+                    );
+                }
+                Err(e) => warn!("invalid pair {} @ {}: {e}", entry.key, entry.revision),
+            },
+            Operation::Delete | Operation::Purge => {
+                debug!("evict pair {} @ {}", entry.key, entry.revision);
+                pairs.remove(&address);
+            }
+        }
+    }
+}
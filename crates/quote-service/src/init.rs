@@ -0,0 +1,99 @@
+use clap::Parser;
+use config::{Config, File};
+use eyre::Result;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+use tracing::debug;
+use tracing_subscriber::{fmt, prelude::*, EnvFilter};
+
+#[derive(Debug, Deserialize)]
+pub struct AppConfig {
+    pub nats: NatsConfig,
+    pub quote: QuoteConfig,
+    pub log: Option<LogConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NatsConfig {
+    pub server_url: String,
+    pub subject_sync: String,
+    pub stream_name: String,
+    pub kv_bucket: String,
+    /// Subject `QuoteRequest`s are sent to.
+    pub subject_request: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct QuoteConfig {
+    /// Swap fee in basis points, 30 for Uniswap V2.
+    pub fee_bps: u32,
+    /// Fee per pair address, for pairs of forks with another fee.
+    #[serde(default)]
+    pub pair_fee_bps: HashMap<String, u32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LogConfig {
+    pub level: String,
+}
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Cli {
+    #[arg(long)]
+    server_url: Option<String>,
+    #[arg(long)]
+    subject_sync: Option<String>,
+    #[arg(long)]
+    stream_name: Option<String>,
+    #[arg(long)]
+    kv_bucket: Option<String>,
+    #[arg(long)]
+    subject_request: Option<String>,
+    #[arg(long)]
+    fee_bps: Option<u32>,
+}
+
+impl AppConfig {
+    pub fn from_file_or_cli() -> Result<Self> {
+        let cli = Cli::parse();
+        let config_path1 = Path::new("config/quote-service.toml");
+        let config_path2 = Path::new("quote-service.toml");
+
+        let cfg: AppConfig = Config::builder()
+            .set_default("quote.fee_bps", 30)?
+            .add_source(File::from(config_path1).required(false))
+            .add_source(File::from(config_path2).required(false))
+            .set_override_option("nats.server_url", cli.server_url)?
+            .set_override_option("nats.subject_sync", cli.subject_sync)?
+            .set_override_option("nats.stream_name", cli.stream_name)?
+            .set_override_option("nats.kv_bucket", cli.kv_bucket)?
+            .set_override_option("nats.subject_request", cli.subject_request)?
+            .set_override_option("quote.fee_bps", cli.fee_bps)?
+            .build()
+            .map_err(eyre::Report::from)?
+            .try_deserialize()?;
+        Ok(cfg)
+    }
+
+    pub fn init_log(&self) -> Result<()> {
+        let level_str = self
+            .log
+            .as_ref()
+            .map(|l| l.level.as_str())
+            .unwrap_or("info");
+
+        let filter = EnvFilter::try_from_default_env()
+            .or_else(|_| EnvFilter::try_new(level_str))
+            .map_err(eyre::Report::from)?;
+
+        tracing_subscriber::registry()
+            .with(fmt::layer())
+            .with(filter)
+            .init();
+
+        debug!("log level configured to: '{}'", level_str);
+        Ok(())
+    }
+}
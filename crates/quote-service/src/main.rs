@@ -0,0 +1,82 @@
+use eyre::{eyre, Result};
+use futures_util::StreamExt;
+use tracing::{debug, info, warn};
+
+use chain_model::{QuoteError, QuoteRequest, SyncEvent};
+
+mod cache;
+mod init;
+mod mq;
+mod quote;
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let app_cfg = init::AppConfig::from_file_or_cli()?;
+    app_cfg.init_log()?;
+    info!("starting quote-service with config: {app_cfg:#?}");
+
+    let mq_client = mq::MqClient::new(&app_cfg.nats.server_url, &app_cfg.nats.stream_name).await?;
+    let kv = mq_client.kv_store(&app_cfg.nats.kv_bucket).await?;
+    let pairs = cache::PairCache::start(&kv).await?;
+    let quoter = quote::Quoter::new(&app_cfg.quote)?;
+
+    let mut syncs = mq_client
+        .jetstream_replay(&app_cfg.nats.subject_sync)
+        .await?;
+    let updates = quoter.clone();
+    tokio::spawn(async move {
+        while let Some(msg_result) = syncs.next().await {
+            match msg_result {
+                Ok(msg) => match serde_json::from_slice::<SyncEvent>(&msg.payload) {
+                    Ok(event) => updates.apply(&event),
+                    Err(e) => warn!("invalid sync payload: {e}"),
+                },
+                Err(e) => warn!("sync stream error: {e}"),
+            }
+        }
+        warn!("sync stream ended, reserves no longer updated");
+    });
+
+    let mut requests = mq_client
+        .subscribe_requests(&app_cfg.nats.subject_request)
+        .await?;
+    info!("answering quotes on '{}'", app_cfg.nats.subject_request);
+    while let Some(msg) = requests.next().await {
+        let Some(reply) = msg.reply.clone() else {
+            debug!("quote request without reply subject, skip");
+            continue;
+        };
+        let payload = match answer(&quoter, &pairs, &msg.payload) {
+            Ok(payload) => payload,
+            Err(e) => {
+                debug!("quote failed: {e}");
+                serde_json::to_string(&QuoteError {
+                    error: e.to_string(),
+                })?
+            }
+        };
+        mq_client.reply(reply, payload).await?;
+    }
+
+    Ok(())
+}
+
+fn answer(quoter: &quote::Quoter, pairs: &cache::PairCache, payload: &[u8]) -> Result<String> {
+    let request: QuoteRequest =
+        serde_json::from_slice(payload).map_err(|e| eyre!("invalid request: {e}"))?;
+    let (pair, _) = pairs
+        .get(&request.pair)
+        .ok_or_else(|| eyre!("pair {} is not tracked", request.pair))?;
+
+    let response = quoter.quote(&request, &pair)?;
+    debug!(
+        "quoted {} {} -> {} {} on {} ({} pairs known)",
+        response.amount_in,
+        response.symbol_in,
+        response.amount_out,
+        response.symbol_out,
+        response.pair,
+        quoter.len()
+    );
+    Ok(serde_json::to_string(&response)?)
+}
@@ -0,0 +1,77 @@
+use async_nats::{
+    jetstream::{self, consumer::DeliverPolicy, kv::Store},
+    Client, Subscriber,
+};
+use eyre::{Result, WrapErr};
+use futures_util::StreamExt;
+
+pub struct MqClient {
+    nats: Client,
+    stream_name: String,
+}
+
+impl MqClient {
+    pub async fn new(server_url: &str, stream_name: &str) -> Result<Self> {
+        let client = async_nats::connect(server_url)
+            .await
+            .map_err(|e| eyre::eyre!("NATS connect failed: {}", e))?;
+        Ok(Self {
+            nats: client,
+            stream_name: stream_name.to_string(),
+        })
+    }
+
+    /// Replays `subject` from the start of the stream on an ephemeral ordered
+    /// consumer; the reserves are rebuilt on every start, so nothing is acked.
+    pub async fn jetstream_replay(
+        &self,
+        subject: &str,
+    ) -> Result<impl StreamExt<Item = Result<async_nats::jetstream::Message>>> {
+        let js = jetstream::new(self.nats.clone());
+
+        let stream = js
+            .get_stream(self.stream_name.clone())
+            .await
+            .wrap_err("Failed to get JetStream stream")?;
+
+        let consumer = stream
+            .create_consumer(async_nats::jetstream::consumer::pull::OrderedConfig {
+                deliver_policy: DeliverPolicy::All,
+                filter_subject: subject.to_string(),
+                ..Default::default()
+            })
+            .await
+            .wrap_err("Failed to create JetStream consumer")?;
+
+        let messages = consumer
+            .messages()
+            .await
+            .wrap_err("Failed to get message stream from consumer")?;
+        Ok(messages.map(|msg_result| {
+            msg_result.map_err(|e| eyre::eyre!("JetStream message error: {}", e))
+        }))
+    }
+
+    /// Requests are spread over every instance in the `quote-service` group.
+    pub async fn subscribe_requests(&self, subject: &str) -> Result<Subscriber> {
+        self.nats
+            .queue_subscribe(subject.to_string(), "quote-service".to_string())
+            .await
+            .map_err(|e| eyre::eyre!("NATS subscribe failed: {}", e))
+    }
+
+    pub async fn reply(&self, subject: async_nats::Subject, record: String) -> Result<()> {
+        self.nats
+            .publish(subject, record.into())
+            .await
+            .map_err(|e| eyre::eyre!("NATS publish failed: {}", e))?;
+        Ok(())
+    }
+
+    pub async fn kv_store(&self, bucket: &str) -> Result<Store> {
+        let js = jetstream::new(self.nats.clone());
+        js.get_key_value(bucket)
+            .await
+            .map_err(|e| eyre::eyre!("KV bucket error: {e}"))
+    }
+}
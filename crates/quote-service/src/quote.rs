@@ -0,0 +1,169 @@
+use alloy::primitives::{
+    utils::{format_units, parse_units},
+    Address, U256,
+};
+use eyre::{eyre, Result};
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+
+use chain_model::{amm, Pair, QuoteRequest, QuoteResponse, SyncEvent};
+
+use crate::init::QuoteConfig;
+
+#[derive(Debug, Clone, Copy)]
+struct Reserves {
+    reserve0: U256,
+    reserve1: U256,
+    block_number: u64,
+}
+
+/// Latest reserves per pair, fed from the Sync stream and read by requests.
+#[derive(Clone)]
+pub struct Quoter {
+    reserves: Arc<RwLock<HashMap<Address, Reserves>>>,
+    fee_bps: u32,
+    pair_fee_bps: Arc<HashMap<Address, u32>>,
+}
+
+impl Quoter {
+    pub fn new(cfg: &QuoteConfig) -> Result<Self> {
+        let pair_fee_bps = cfg
+            .pair_fee_bps
+            .iter()
+            .map(|(pair, fee)| Ok((Address::from_str(pair)?, *fee)))
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            reserves: Arc::default(),
+            fee_bps: cfg.fee_bps,
+            pair_fee_bps: Arc::new(pair_fee_bps),
+        })
+    }
+
+    /// Keeps the reserves of `event` unless newer ones are already known.
+    pub fn apply(&self, event: &SyncEvent) {
+        let mut reserves = self.reserves.write().unwrap();
+        if reserves
+            .get(&event.pair)
+            .is_some_and(|known| known.block_number > event.block_number)
+        {
+            return;
+        }
+        reserves.insert(
+            event.pair,
+            Reserves {
+                reserve0: U256::from(event.reserve0),
+                reserve1: U256::from(event.reserve1),
+                block_number: event.block_number,
+            },
+        );
+    }
+
+    pub fn len(&self) -> usize {
+        self.reserves.read().unwrap().len()
+    }
+
+    pub fn quote(&self, request: &QuoteRequest, pair: &Pair) -> Result<QuoteResponse> {
+        let reserves = self
+            .reserves
+            .read()
+            .unwrap()
+            .get(&request.pair)
+            .copied()
+            .ok_or_else(|| eyre!("no reserves known for pair {}", request.pair))?;
+
+        let (token_in, token_out, reserve_in, reserve_out) =
+            if request.token_in == pair.token0.address {
+                (
+                    &pair.token0,
+                    &pair.token1,
+                    reserves.reserve0,
+                    reserves.reserve1,
+                )
+            } else if request.token_in == pair.token1.address {
+                (
+                    &pair.token1,
+                    &pair.token0,
+                    reserves.reserve1,
+                    reserves.reserve0,
+                )
+            } else {
+                return Err(eyre!(
+                    "token {} is not part of pair {}",
+                    request.token_in,
+                    request.pair
+                ));
+            };
+        let fee_bps = self
+            .pair_fee_bps
+            .get(&request.pair)
+            .copied()
+            .unwrap_or(self.fee_bps);
+
+        let (amount_in_raw, amount_out_raw) = match (&request.amount_in, &request.amount_out) {
+            (Some(amount_in), None) => {
+                let amount_in = positive_units(amount_in, token_in.decimals, "amount_in")?;
+                let amount_out = amm::get_amount_out(amount_in, reserve_in, reserve_out, fee_bps)
+                    .ok_or_else(|| eyre!("amount_in {amount_in} cannot be quoted"))?;
+                (amount_in, amount_out)
+            }
+            (None, Some(amount_out)) => {
+                let amount_out = positive_units(amount_out, token_out.decimals, "amount_out")?;
+                let amount_in = amm::get_amount_in(amount_out, reserve_in, reserve_out, fee_bps)
+                    .ok_or_else(|| eyre!("amount_out {amount_out} exceeds the reserve"))?;
+                (amount_in, amount_out)
+            }
+            _ => return Err(eyre!("set exactly one of amount_in and amount_out")),
+        };
+
+        let units = |amount: U256, decimals: u8| f64::from(amount) / 10f64.powi(decimals as i32);
+        let mid_price =
+            units(reserve_out, token_out.decimals) / units(reserve_in, token_in.decimals);
+        let execution_price =
+            units(amount_out_raw, token_out.decimals) / units(amount_in_raw, token_in.decimals);
+
+        Ok(QuoteResponse {
+            pair: request.pair,
+            token_in: token_in.address,
+            token_out: token_out.address,
+            symbol_in: token_in.symbol.clone(),
+            symbol_out: token_out.symbol.clone(),
+            amount_in: format_units(amount_in_raw, token_in.decimals)?,
+            amount_out: format_units(amount_out_raw, token_out.decimals)?,
+            amount_in_raw,
+            amount_out_raw,
+            fee_bps,
+            mid_price,
+            execution_price,
+            price_impact_percent: (1.0 - execution_price / mid_price) * 100.0,
+            reserve_in,
+            reserve_out,
+            block_number: reserves.block_number,
+        })
+    }
+}
+
+/// Parses a requested amount in whole tokens, which must be above zero.
+fn positive_units(amount: &str, decimals: u8, field: &str) -> Result<U256> {
+    let parsed = parse_units(amount, decimals)?;
+    if parsed.is_negative() || parsed.is_zero() {
+        return Err(eyre!("{field} must be above zero, got {amount}"));
+    }
+    Ok(parsed.get_absolute())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn amounts_must_be_above_zero() {
+        assert_eq!(
+            positive_units("1.5", 6, "amount_in").unwrap(),
+            U256::from(1_500_000)
+        );
+        assert!(positive_units("-5", 18, "amount_in").is_err());
+        assert!(positive_units("0", 18, "amount_in").is_err());
+        assert!(positive_units("0.0", 6, "amount_out").is_err());
+    }
+}
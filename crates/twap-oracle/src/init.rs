@@ -0,0 +1,99 @@
+use clap::Parser;
+use config::{Config, File};
+use eyre::Result;
+use serde::Deserialize;
+use std::path::Path;
+use tracing::debug;
+use tracing_subscriber::{fmt, prelude::*, EnvFilter};
+
+#[derive(Debug, Deserialize)]
+pub struct AppConfig {
+    pub nats: NatsConfig,
+    pub twap: TwapConfig,
+    pub log: Option<LogConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NatsConfig {
+    pub server_url: String,
+    pub stream_name: String,
+    pub subject_sync: String,
+    /// Swap events for VWAP; without it only TWAPs are computed.
+    pub subject_swap: Option<String>,
+    pub subject_output: String,
+    pub kv_bucket: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TwapConfig {
+    /// Window labels such as `5m`, `30m` or `1h`.
+    pub windows: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LogConfig {
+    pub level: String,
+}
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Cli {
+    #[arg(long)]
+    server_url: Option<String>,
+    #[arg(long)]
+    stream_name: Option<String>,
+    #[arg(long)]
+    subject_sync: Option<String>,
+    #[arg(long)]
+    subject_swap: Option<String>,
+    #[arg(long)]
+    subject_output: Option<String>,
+    #[arg(long)]
+    kv_bucket: Option<String>,
+    #[arg(long)]
+    window: Option<Vec<String>>,
+}
+
+impl AppConfig {
+    pub fn from_file_or_cli() -> Result<Self> {
+        let cli = Cli::parse();
+        let config_path1 = Path::new("config/twap-oracle.toml");
+        let config_path2 = Path::new("twap-oracle.toml");
+
+        let cfg: AppConfig = Config::builder()
+            .set_default("twap.windows", vec!["5m", "30m", "1h"])?
+            .add_source(File::from(config_path1).required(false))
+            .add_source(File::from(config_path2).required(false))
+            .set_override_option("nats.server_url", cli.server_url)?
+            .set_override_option("nats.stream_name", cli.stream_name)?
+            .set_override_option("nats.subject_sync", cli.subject_sync)?
+            .set_override_option("nats.subject_swap", cli.subject_swap)?
+            .set_override_option("nats.subject_output", cli.subject_output)?
+            .set_override_option("nats.kv_bucket", cli.kv_bucket)?
+            .set_override_option("twap.windows", cli.window)?
+            .build()
+            .map_err(eyre::Report::from)?
+            .try_deserialize()?;
+        Ok(cfg)
+    }
+
+    pub fn init_log(&self) -> Result<()> {
+        let level_str = self
+            .log
+            .as_ref()
+            .map(|l| l.level.as_str())
+            .unwrap_or("info");
+
+        let filter = EnvFilter::try_from_default_env()
+            .or_else(|_| EnvFilter::try_new(level_str))
+            .map_err(eyre::Report::from)?;
+
+        tracing_subscriber::registry()
+            .with(fmt::layer())
+            .with(filter)
+            .init();
+
+        debug!("log level configured to: '{}'", level_str);
+        Ok(())
+    }
+}
@@ -0,0 +1,66 @@
+use eyre::{eyre, Result};
+use futures_util::StreamExt;
+use tracing::{debug, info, warn};
+
+use chain_model::{SwapEvent, SyncEvent};
+
+mod cache;
+mod init;
+mod mq;
+mod oracle;
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let app_cfg = init::AppConfig::from_file_or_cli()?;
+    app_cfg.init_log()?;
+    info!("starting twap-oracle with config: {app_cfg:#?}");
+
+    let mq_client = mq::MqClient::new(
+        &app_cfg.nats.server_url,
+        &app_cfg.nats.subject_output,
+        &app_cfg.nats.stream_name,
+    )
+    .await?;
+    let kv = mq_client.kv_store(&app_cfg.nats.kv_bucket).await?;
+    // Watch-backed, so admin updates of a pair apply without a restart.
+    let pairs = cache::PairCache::start(&kv).await?;
+
+    let mut oracle = oracle::Oracle::new(&app_cfg.twap)?;
+
+    let mut subjects = vec![app_cfg.nats.subject_sync.clone()];
+    subjects.extend(app_cfg.nats.subject_swap.clone());
+    let mut sub = mq_client.jetstream_pull_from(subjects, true).await?;
+    while let Some(msg_result) = sub.next().await {
+        let msg = msg_result?;
+
+        if msg.subject.as_str() == app_cfg.nats.subject_sync {
+            match serde_json::from_slice::<SyncEvent>(&msg.payload) {
+                Ok(event) => {
+                    if !oracle.on_sync(&event) {
+                        debug!("stale sync of {} at {}", event.pair, event.block_number);
+                    } else if let Some((pair, _)) = pairs.get(&event.pair) {
+                        for average in oracle.averages(&pair) {
+                            debug!("average price: {average:?}");
+                            let payload = serde_json::to_string(&average)?;
+                            mq_client.produce_record(payload).await?;
+                        }
+                    } else {
+                        debug!("pair {} not found in kv store", event.pair);
+                    }
+                }
+                Err(e) => warn!("invalid sync payload: {e}"),
+            }
+        } else {
+            match serde_json::from_slice::<SwapEvent>(&msg.payload) {
+                Ok(event) => oracle.on_swap(&event),
+                Err(e) => warn!("invalid swap payload: {e}"),
+            }
+        }
+
+        msg.ack()
+            .await
+            .map_err(|e| eyre!("ack message failed: {e}"))?;
+    }
+
+    Ok(())
+}
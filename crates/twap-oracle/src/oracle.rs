@@ -0,0 +1,376 @@
+use alloy::primitives::{Address, U256};
+use eyre::{eyre, Result};
+use std::collections::{HashMap, VecDeque};
+
+use chain_model::{interval, AveragePrice, Pair, SwapEvent, SyncEvent};
+
+use crate::init::TwapConfig;
+
+/// `2**112`, the UQ112x112 resolution of the cumulative prices.
+const Q112: f64 = 5192296858534827628530496329220096.0;
+
+struct Window {
+    label: String,
+    secs: u32,
+}
+
+/// Cumulative prices as of the first Sync of a block.
+struct Observation {
+    timestamp: u32,
+    price0_cumulative: U256,
+    price1_cumulative: U256,
+}
+
+struct Swap {
+    timestamp: u64,
+    amount0: f64,
+    amount1: f64,
+}
+
+/// Mirror of a pair's `price{0,1}CumulativeLast` bookkeeping.
+#[derive(Default)]
+struct PairState {
+    reserve0: U256,
+    reserve1: U256,
+    block_number: u64,
+    block_timestamp: u64,
+    block_timestamp_last: u32,
+    price0_cumulative_last: U256,
+    price1_cumulative_last: U256,
+    observations: VecDeque<Observation>,
+    swaps: VecDeque<Swap>,
+}
+
+impl PairState {
+    /// `UniswapV2Pair._update`: the reserves held before this block are
+    /// accumulated for the time elapsed since the previous update, with the
+    /// same uint32 timestamp and uint256 overflow semantics.
+    fn update(&mut self, event: &SyncEvent) {
+        let block_timestamp = event.block_timestamp as u32;
+        let time_elapsed = block_timestamp.wrapping_sub(self.block_timestamp_last);
+        if time_elapsed > 0 && !self.reserve0.is_zero() && !self.reserve1.is_zero() {
+            let elapsed = U256::from(time_elapsed);
+            self.price0_cumulative_last = self
+                .price0_cumulative_last
+                .wrapping_add(((self.reserve1 << 112usize) / self.reserve0).wrapping_mul(elapsed));
+            self.price1_cumulative_last = self
+                .price1_cumulative_last
+                .wrapping_add(((self.reserve0 << 112usize) / self.reserve1).wrapping_mul(elapsed));
+        }
+        self.reserve0 = U256::from(event.reserve0);
+        self.reserve1 = U256::from(event.reserve1);
+        self.block_timestamp_last = block_timestamp;
+        self.block_number = event.block_number;
+        self.block_timestamp = event.block_timestamp;
+
+        if self
+            .observations
+            .back()
+            .is_none_or(|last| last.timestamp != block_timestamp)
+        {
+            self.observations.push_back(Observation {
+                timestamp: block_timestamp,
+                price0_cumulative: self.price0_cumulative_last,
+                price1_cumulative: self.price1_cumulative_last,
+            });
+        }
+    }
+
+    /// Drops observations and swaps no window reaches back to anymore.
+    fn prune(&mut self, now: u64, max_window: u32) {
+        let now32 = now as u32;
+        while self.observations.len() > 1
+            && now32.wrapping_sub(self.observations[1].timestamp) >= max_window
+        {
+            self.observations.pop_front();
+        }
+        while self
+            .swaps
+            .front()
+            .is_some_and(|swap| swap.timestamp + max_window as u64 <= now)
+        {
+            self.swaps.pop_front();
+        }
+    }
+}
+
+/// Per-pair TWAPs following Uniswap V2's cumulative price accumulator, and
+/// VWAPs from Swap amounts, over every configured window.
+pub struct Oracle {
+    windows: Vec<Window>,
+    pairs: HashMap<Address, PairState>,
+}
+
+impl Oracle {
+    pub fn new(cfg: &TwapConfig) -> Result<Self> {
+        let windows = cfg
+            .windows
+            .iter()
+            .map(|label| {
+                Ok(Window {
+                    label: label.clone(),
+                    secs: interval::parse_secs(label)
+                        .and_then(|secs| u32::try_from(secs).ok())
+                        .ok_or_else(|| eyre!("invalid twap window '{label}'"))?,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if windows.is_empty() {
+            return Err(eyre!("no twap windows configured"));
+        }
+        Ok(Self {
+            windows,
+            pairs: HashMap::new(),
+        })
+    }
+
+    /// Returns `false` for events older than the last one applied to the pair.
+    pub fn on_sync(&mut self, event: &SyncEvent) -> bool {
+        let state = self.pairs.entry(event.pair).or_default();
+        if event.block_number < state.block_number {
+            return false;
+        }
+        state.update(event);
+
+        let max_window = self.windows.iter().map(|w| w.secs).max().unwrap_or(0);
+        state.prune(event.block_timestamp, max_window);
+        true
+    }
+
+    pub fn on_swap(&mut self, event: &SwapEvent) {
+        let state = self.pairs.entry(event.pair).or_default();
+        state.swaps.push_back(Swap {
+            timestamp: event.block_timestamp,
+            amount0: f64::from(event.amount0_in + event.amount0_out),
+            amount1: f64::from(event.amount1_in + event.amount1_out),
+        });
+    }
+
+    /// Averages of `pair` for every window, as of its latest Sync. Windows
+    /// without any elapsed time yet are left out.
+    pub fn averages(&self, pair: &Pair) -> Vec<AveragePrice> {
+        let Some(state) = self.pairs.get(&pair.address) else {
+            return Vec::new();
+        };
+        let now = state.block_timestamp_last;
+        // Decimal adjustment from raw reserve ratios to whole-token prices.
+        let scale0 = 10f64.powi(pair.token0.decimals as i32 - pair.token1.decimals as i32);
+        let unit0 = 10f64.powi(pair.token0.decimals as i32);
+        let unit1 = 10f64.powi(pair.token1.decimals as i32);
+
+        let mut averages = Vec::new();
+        for window in &self.windows {
+            // Newest observation at least a full window old, else the oldest.
+            let Some(start) = state
+                .observations
+                .iter()
+                .rev()
+                .find(|obs| now.wrapping_sub(obs.timestamp) >= window.secs)
+                .or_else(|| state.observations.front())
+            else {
+                continue;
+            };
+            let elapsed = now.wrapping_sub(start.timestamp);
+            if elapsed == 0 {
+                continue;
+            }
+
+            let twap = |current: U256, start: U256| {
+                f64::from(current.wrapping_sub(start) / U256::from(elapsed)) / Q112
+            };
+            let token0_twap = twap(state.price0_cumulative_last, start.price0_cumulative) * scale0;
+            let token1_twap = twap(state.price1_cumulative_last, start.price1_cumulative) / scale0;
+
+            let since = state.block_timestamp.saturating_sub(window.secs as u64);
+            let (amount0, amount1) = state
+                .swaps
+                .iter()
+                .filter(|swap| swap.timestamp > since)
+                .fold((0.0, 0.0), |(a0, a1), swap| {
+                    (a0 + swap.amount0, a1 + swap.amount1)
+                });
+            let token0_volume = amount0 / unit0;
+            let token1_volume = amount1 / unit1;
+            let token0_vwap = (token0_volume > 0.0).then(|| token1_volume / token0_volume);
+
+            averages.push(AveragePrice {
+                pair_address: pair.address.to_string(),
+                token0_symbol: pair.token0.symbol.clone(),
+                token1_symbol: pair.token1.symbol.clone(),
+                window: window.label.clone(),
+                elapsed: elapsed as u64,
+                token0_twap,
+                token1_twap,
+                token0_vwap,
+                token0_volume,
+                token1_volume,
+                block_number: state.block_number,
+                block_timestamp: state.block_timestamp,
+            });
+        }
+        averages
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy::primitives::{address, FixedBytes};
+    use chain_model::Token;
+
+    const PAIR: Address = address!("B4e16d0168e52d35CaCD2c6185b44281Ec28C9Dc");
+    const WETH: u128 = 1_000_000_000_000_000_000;
+    const USDC: u128 = 1_000_000;
+
+    fn oracle(windows: &[&str]) -> Oracle {
+        Oracle::new(&TwapConfig {
+            windows: windows.iter().map(|w| w.to_string()).collect(),
+        })
+        .unwrap()
+    }
+
+    /// WETH as token0 and USDC as token1.
+    fn pair() -> Pair {
+        let token = |symbol: &str, decimals| Token {
+            address: Address::ZERO,
+            decimals,
+            symbol: symbol.to_string(),
+            total_supply: U256::ZERO,
+            verified: true,
+            risk: None,
+        };
+        Pair {
+            address: PAIR,
+            token0: token("WETH", 18),
+            token1: token("USDC", 6),
+            factory: None,
+            created_block: None,
+        }
+    }
+
+    /// A Sync leaving one WETH against `usdc` USDC.
+    fn sync(block_number: u64, block_timestamp: u64, usdc: u128) -> SyncEvent {
+        SyncEvent {
+            pair: PAIR,
+            reserve0: U256::from(WETH).to(),
+            reserve1: U256::from(usdc * USDC).to(),
+            transaction_hash: FixedBytes::ZERO,
+            log_index: Some(0),
+            block_number,
+            block_timestamp,
+            snapshot: false,
+        }
+    }
+
+    fn swap(block_timestamp: u64, weth_in: u128, usdc_out: u128) -> SwapEvent {
+        SwapEvent {
+            pair: PAIR,
+            sender: Address::ZERO,
+            to: Address::ZERO,
+            amount0_in: U256::from(weth_in),
+            amount1_in: U256::ZERO,
+            amount0_out: U256::ZERO,
+            amount1_out: U256::from(usdc_out),
+            transaction_hash: FixedBytes::ZERO,
+            block_number: 0,
+            block_timestamp,
+        }
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() <= expected.abs() * 1e-9,
+            "{actual} != {expected}"
+        );
+    }
+
+    #[test]
+    fn constant_reserves_average_to_the_spot_price() {
+        let mut oracle = oracle(&["1m"]);
+        for (block, timestamp) in [(1, 1_000), (6, 1_060), (11, 1_120)] {
+            assert!(oracle.on_sync(&sync(block, timestamp, 2500)));
+        }
+        let averages = oracle.averages(&pair());
+        assert_eq!(averages.len(), 1);
+        assert_eq!(averages[0].elapsed, 60);
+        assert_close(averages[0].token0_twap, 2500.0);
+        assert_close(averages[0].token1_twap, 1.0 / 2500.0);
+    }
+
+    #[test]
+    fn prices_are_weighted_by_the_time_they_held() {
+        let mut oracle = oracle(&["1m"]);
+        oracle.on_sync(&sync(1, 1_000, 2000));
+        // 2000 held for 45s, then 3000 for 15s.
+        oracle.on_sync(&sync(2, 1_045, 3000));
+        oracle.on_sync(&sync(3, 1_060, 3000));
+
+        let average = &oracle.averages(&pair())[0];
+        assert_close(average.token0_twap, (2000.0 * 45.0 + 3000.0 * 15.0) / 60.0);
+        assert_close(average.token1_twap, (45.0 / 2000.0 + 15.0 / 3000.0) / 60.0);
+    }
+
+    #[test]
+    fn only_the_reserves_before_a_block_accumulate() {
+        let mut oracle = oracle(&["1m"]);
+        oracle.on_sync(&sync(1, 1_000, 2000));
+        // Two Syncs in one block: the first price never held for any time.
+        oracle.on_sync(&sync(2, 1_030, 9000));
+        oracle.on_sync(&sync(2, 1_030, 2000));
+        oracle.on_sync(&sync(3, 1_060, 2000));
+
+        assert_close(oracle.averages(&pair())[0].token0_twap, 2000.0);
+    }
+
+    #[test]
+    fn windows_cover_at_most_their_length() {
+        let mut oracle = oracle(&["1m", "5m"]);
+        oracle.on_sync(&sync(1, 1_000, 1000));
+        oracle.on_sync(&sync(2, 1_060, 2000));
+        oracle.on_sync(&sync(3, 1_120, 2000));
+
+        let averages = oracle.averages(&pair());
+        let (short, long) = (&averages[0], &averages[1]);
+        assert_eq!((short.window.as_str(), short.elapsed), ("1m", 60));
+        assert_close(short.token0_twap, 2000.0);
+        // Still building up: the 5m window covers the 120s seen so far.
+        assert_eq!((long.window.as_str(), long.elapsed), ("5m", 120));
+        assert_close(long.token0_twap, 1500.0);
+    }
+
+    #[test]
+    fn uint32_timestamps_wrap_like_the_pair() {
+        let wrap = u32::MAX as u64 + 1;
+        let mut oracle = oracle(&["1m"]);
+        oracle.on_sync(&sync(1, wrap - 30, 2500));
+        oracle.on_sync(&sync(2, wrap + 30, 2500));
+
+        let average = &oracle.averages(&pair())[0];
+        assert_eq!(average.elapsed, 60);
+        assert_close(average.token0_twap, 2500.0);
+    }
+
+    #[test]
+    fn older_syncs_are_ignored() {
+        let mut oracle = oracle(&["1m"]);
+        assert!(oracle.on_sync(&sync(10, 1_000, 2500)));
+        assert!(!oracle.on_sync(&sync(9, 990, 1)));
+        assert!(oracle.averages(&pair()).is_empty());
+    }
+
+    #[test]
+    fn vwap_covers_the_swaps_in_the_window() {
+        let mut oracle = oracle(&["1m"]);
+        oracle.on_sync(&sync(1, 1_000, 2500));
+        // Outside the window by the time of the last Sync.
+        oracle.on_swap(&swap(1_000, WETH, 9000 * USDC));
+        oracle.on_swap(&swap(1_050, WETH, 2400 * USDC));
+        oracle.on_swap(&swap(1_055, 3 * WETH, 7800 * USDC));
+        oracle.on_sync(&sync(2, 1_060, 2500));
+
+        let average = &oracle.averages(&pair())[0];
+        assert_close(average.token0_volume, 4.0);
+        assert_close(average.token1_volume, 10_200.0);
+        assert_close(average.token0_vwap.unwrap(), 2550.0);
+    }
+}
@@ -40,6 +40,8 @@ pub struct Cli {
     command: Commands,
 }
 
+// Variant names are the subcommand names, e.g. `sync-event`.
+#[allow(clippy::enum_variant_names)]
 #[derive(Subcommand, Debug, Clone)]
 pub enum Commands {
     PairCreatedEvent {
@@ -63,6 +65,17 @@ pub enum Commands {
         #[arg(long)]
         kv_bucket: Option<String>,
     },
+
+    SwapEvent {
+        #[arg(long)]
+        ws_url: Option<String>,
+        #[arg(long)]
+        server_url: Option<String>,
+        #[arg(long)]
+        subject_name: Option<String>,
+        #[arg(long)]
+        kv_bucket: Option<String>,
+    },
 }
 
 impl AppConfig {
@@ -94,6 +107,12 @@ impl AppConfig {
                 server_url,
                 subject_name,
                 kv_bucket,
+            }
+            | Commands::SwapEvent {
+                ws_url,
+                server_url,
+                subject_name,
+                kv_bucket,
             } => {
                 builder = builder
                     .set_override_option("eth_node.ws_url", ws_url)?
@@ -6,7 +6,7 @@ use serde_json::json;
 use std::str::FromStr;
 use tracing::{error, info, warn};
 
-use chain_model::{PairCreatedEvent, SyncEvent};
+use chain_model::{PairCreatedEvent, SwapEvent, SyncEvent};
 use init::AppConfig;
 use init::Commands;
 use uni::{UniswapV2Factory, UniswapV2Pair};
@@ -24,6 +24,7 @@ async fn main() -> Result<()> {
     match app_cmd {
         Commands::PairCreatedEvent { .. } => run_pair_created(app_cfg).await?,
         Commands::SyncEvent { .. } => run_sync_event(app_cfg).await?,
+        Commands::SwapEvent { .. } => run_swap_event(app_cfg).await?,
     }
 
     Ok(())
@@ -53,6 +54,7 @@ async fn run_pair_created(app_cfg: AppConfig) -> Result<()> {
                     pair: event.pair,
                     token0: event.token0,
                     token1: event.token1,
+                    factory: Some(rpc_log.address()),
                     transaction_hash: rpc_log.transaction_hash.unwrap_or_default(),
                     block_number: rpc_log.block_number.unwrap_or_default(),
                     block_timestamp: rpc_log.block_timestamp.unwrap_or_default(),
@@ -72,19 +74,7 @@ async fn run_sync_event(app_cfg: AppConfig) -> Result<()> {
     let mq_client = mq::MqClient::new(&app_cfg.nats.server_url, &app_cfg.nats.subject_name).await?;
 
     let kv_bucket = app_cfg.nats.kv_bucket.unwrap_or_default();
-    let kv_store = mq_client.get_kv(&kv_bucket).await?;
-
-    let mut pair_addresses = Vec::new();
-    let mut keys = kv_store.keys().await?;
-    while let Some(key_result) = keys.next().await {
-        match key_result {
-            Ok(key) => match Address::from_str(&key) {
-                Ok(addr) => pair_addresses.push(addr),
-                Err(e) => warn!("Failed to parse key '{key}' as address: {e}"),
-            },
-            Err(e) => error!("Failed to get key from KV store: {e}"),
-        }
-    }
+    let pair_addresses = tracked_pairs(&mq_client, &kv_bucket).await?;
     info!(
         "Found {} pairs in KV store '{}'. Subscribing to their Sync events.",
         pair_addresses.len(),
@@ -106,6 +96,49 @@ async fn run_sync_event(app_cfg: AppConfig) -> Result<()> {
                     reserve0: event.reserve0,
                     reserve1: event.reserve1,
                     transaction_hash: rpc_log.transaction_hash.unwrap_or_default(),
+                    log_index: rpc_log.log_index,
+                    block_number: rpc_log.block_number.unwrap_or_default(),
+                    block_timestamp: rpc_log.block_timestamp.unwrap_or_default(),
+                    snapshot: false,
+                };
+                let msg = serde_json::to_string(&payload)?;
+                info!("Sending event: {msg}");
+                mq_client.produce_record(msg).await?;
+            }
+            Err(e) => warn!("Decode failed: {e}"),
+        }
+    }
+    Ok(())
+}
+
+async fn run_swap_event(app_cfg: AppConfig) -> Result<()> {
+    let uniswap = uni::UniswapV2::new(&app_cfg.eth_node.ws_url, Address::ZERO).await?;
+    let mq_client = mq::MqClient::new(&app_cfg.nats.server_url, &app_cfg.nats.subject_name).await?;
+
+    let kv_bucket = app_cfg.nats.kv_bucket.unwrap_or_default();
+    let pair_addresses = tracked_pairs(&mq_client, &kv_bucket).await?;
+    info!(
+        "Found {} pairs in KV store '{}'. Subscribing to their Swap events.",
+        pair_addresses.len(),
+        kv_bucket
+    );
+
+    let mut stream_event = uniswap.subscribe_swap_event(pair_addresses).await?;
+    info!("Listening for Swap events…");
+
+    while let Some(rpc_log) = stream_event.next().await {
+        let primitives_log = rpc_log.clone().into();
+        match UniswapV2Pair::Swap::decode_log(&primitives_log) {
+            Ok(event) => {
+                let payload = SwapEvent {
+                    pair: event.address,
+                    sender: event.sender,
+                    to: event.to,
+                    amount0_in: event.amount0In,
+                    amount1_in: event.amount1In,
+                    amount0_out: event.amount0Out,
+                    amount1_out: event.amount1Out,
+                    transaction_hash: rpc_log.transaction_hash.unwrap_or_default(),
                     block_number: rpc_log.block_number.unwrap_or_default(),
                     block_timestamp: rpc_log.block_timestamp.unwrap_or_default(),
                 };
@@ -118,3 +151,21 @@ async fn run_sync_event(app_cfg: AppConfig) -> Result<()> {
     }
     Ok(())
 }
+
+/// Addresses of the pairs stored in `kv_bucket`.
+async fn tracked_pairs(mq_client: &mq::MqClient, kv_bucket: &str) -> Result<Vec<Address>> {
+    let kv_store = mq_client.get_kv(kv_bucket).await?;
+
+    let mut pair_addresses = Vec::new();
+    let mut keys = kv_store.keys().await?;
+    while let Some(key_result) = keys.next().await {
+        match key_result {
+            Ok(key) => match Address::from_str(&key) {
+                Ok(addr) => pair_addresses.push(addr),
+                Err(e) => warn!("Failed to parse key '{key}' as address: {e}"),
+            },
+            Err(e) => error!("Failed to get key from KV store: {e}"),
+        }
+    }
+    Ok(pair_addresses)
+}
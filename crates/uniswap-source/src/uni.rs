@@ -72,4 +72,17 @@ impl UniswapV2 {
         let sub = self.ws_provider.subscribe_logs(&filter).await?;
         Ok(sub.into_stream())
     }
+
+    pub async fn subscribe_swap_event(
+        &self,
+        pair_addresses: Vec<Address>,
+    ) -> Result<impl StreamExt<Item = Log>> {
+        let event_signature = UniswapV2Pair::Swap::SIGNATURE_HASH;
+        let filter = Filter::new()
+            .event_signature(event_signature)
+            .address(pair_addresses);
+
+        let sub = self.ws_provider.subscribe_logs(&filter).await?;
+        Ok(sub.into_stream())
+    }
 }